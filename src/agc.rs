@@ -0,0 +1,321 @@
+//! Software automatic gain control.
+//!
+//! The WM8731 has no hardware level control. [`Agc`] measure blocks of ADC samples and choose
+//! the line input volume keeping the level close to a target, by 1.5dB steps. The microphone
+//! input doesn't go through the line input volume, so when it is selected the AGC can only toggle
+//! `MICBOOST` (+20dB).
+//!
+//! [`Agc::process`] only compute decisions, so the AGC can be tested on the host with synthetic
+//! signals. [`Agc::update`] also synchronise with the codec and write the decisions.
+use crate::db::Centibels;
+use crate::gain::Gain;
+use crate::interface::WriteFrame;
+use crate::math;
+use crate::{InVoldB, InselV, IwlV, Wm8731};
+
+/// Gain of `MICBOOST`.
+const MICBOOST_CB: i16 = 200;
+/// Tolerance around the target, half a step.
+const HYSTERESIS_CB: i16 = 7;
+
+/// Level measurement used to adjust the gain.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Detector {
+    /// Greatest sample magnitude of a block. Reacts to transients.
+    Peak,
+    /// Root mean square of a block. Closer to perceived loudness.
+    Rms,
+}
+
+/// AGC parameters. Levels are in dBFS.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct AgcConfig {
+    pub detector: Detector,
+    /// Level to maintain.
+    pub target: Centibels,
+    /// Below this level, signal is considered as noise and gain is not increased.
+    pub noise_gate: Centibels,
+    /// Time per 1.5dB step when reducing gain. 0 reduce gain immediately.
+    pub attack_ms: u32,
+    /// Time per 1.5dB step when increasing gain.
+    pub decay_ms: u32,
+    /// Time without loud level to wait before increasing gain.
+    pub hold_ms: u32,
+    pub min_gain: InVoldB,
+    pub max_gain: InVoldB,
+    /// Allow toggling `MICBOOST` when the microphone is selected.
+    pub micboost: bool,
+}
+
+impl AgcConfig {
+    /// Peak detection at -12dBFS, with fast attack and slow decay.
+    pub const DEFAULT: AgcConfig = AgcConfig {
+        detector: Detector::Peak,
+        target: Centibels(-120),
+        noise_gate: Centibels(-600),
+        attack_ms: 5,
+        decay_ms: 100,
+        hold_ms: 500,
+        min_gain: InVoldB::MIN,
+        max_gain: InVoldB::MAX,
+        micboost: false,
+    };
+}
+
+impl Default for AgcConfig {
+    fn default() -> Self {
+        AgcConfig::DEFAULT
+    }
+}
+
+/// Automatic gain controller.
+///
+/// Samples are interleaved stereo, sign extended at the interface word length. Both channels
+/// share the same gain.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct Agc {
+    config: AgcConfig,
+    sample_rate_hz: u32,
+    full_scale: u32,
+    input: InselV,
+    gain: InVoldB,
+    micboost: bool,
+    peak: Centibels,
+    rms: Centibels,
+    attack_us: u32,
+    decay_us: u32,
+    hold_us: u32,
+}
+
+impl Agc {
+    /// New AGC for line input, starting at 0dB without `MICBOOST`.
+    pub fn new(config: AgcConfig, sample_rate_hz: u32, iwl: IwlV) -> Self {
+        Self {
+            config,
+            sample_rate_hz,
+            full_scale: iwl.full_scale(),
+            input: InselV::Line,
+            gain: InVoldB::Z0DB,
+            micboost: false,
+            peak: Centibels(i16::MIN),
+            rms: Centibels(i16::MIN),
+            attack_us: 0,
+            decay_us: 0,
+            hold_us: 0,
+        }
+    }
+
+    pub fn config(&self) -> AgcConfig {
+        self.config
+    }
+
+    pub fn set_config(&mut self, config: AgcConfig) {
+        self.config = config;
+    }
+
+    pub fn input(&self) -> InselV {
+        self.input
+    }
+
+    pub fn set_input(&mut self, input: InselV) {
+        self.input = input;
+    }
+
+    /// Line input volume chosen by the AGC.
+    pub fn gain(&self) -> InVoldB {
+        self.gain
+    }
+
+    pub fn set_gain(&mut self, gain: InVoldB) {
+        self.gain = gain;
+    }
+
+    /// `MICBOOST` chosen by the AGC.
+    pub fn micboost(&self) -> bool {
+        self.micboost
+    }
+
+    pub fn set_micboost(&mut self, micboost: bool) {
+        self.micboost = micboost;
+    }
+
+    /// Peak level of the last block.
+    pub fn peak_dbfs(&self) -> Centibels {
+        self.peak
+    }
+
+    /// RMS level of the last block.
+    pub fn rms_dbfs(&self) -> Centibels {
+        self.rms
+    }
+
+    /// Measure a block of interleaved stereo samples and adjust gain. Return `true` if the gain or
+    /// `MICBOOST` changed.
+    pub fn process(&mut self, samples: &[i32]) -> bool {
+        let frames = (samples.len() / 2) as u64;
+        let block_us = (frames * 1_000_000 / self.sample_rate_hz.max(1) as u64) as u32;
+        self.peak = Centibels::from_ratio(math::peak(samples), self.full_scale);
+        self.rms = Centibels::from_ratio(math::rms(samples), self.full_scale);
+        let level = match self.config.detector {
+            Detector::Peak => self.peak,
+            Detector::Rms => self.rms,
+        };
+        let attack_step_us = self.config.attack_ms.saturating_mul(1000);
+        let decay_step_us = self.config.decay_ms.saturating_mul(1000);
+        self.attack_us = self.attack_us.saturating_add(block_us);
+        self.decay_us = self.decay_us.saturating_add(block_us);
+        self.hold_us = self.hold_us.saturating_sub(block_us);
+        let target = self.config.target.0 as i32;
+        let level = level.0 as i32;
+        let (gain, micboost) = (self.gain, self.micboost);
+        if level > target + HYSTERESIS_CB as i32 {
+            self.hold_us = self.config.hold_ms.saturating_mul(1000);
+            self.decay_us = 0;
+            let wanted = ((level - target) as u32).div_ceil(InVoldB::STEP.0 as u32);
+            let steps = match attack_step_us {
+                0 => wanted,
+                step_us => wanted.min(self.attack_us / step_us),
+            };
+            if steps > 0 {
+                self.attack_us = 0;
+                self.reduce(steps.min(u8::MAX as u32) as u8);
+            }
+        } else {
+            // don't let a burst of steps build up while the level is fine
+            self.attack_us = self.attack_us.min(attack_step_us);
+            let quiet = level < target - HYSTERESIS_CB as i32;
+            let gated = level < self.config.noise_gate.0 as i32;
+            if quiet && !gated && self.hold_us == 0 && self.decay_us >= decay_step_us {
+                self.decay_us = 0;
+                self.raise(target - level);
+            }
+        }
+        (gain, micboost) != (self.gain, self.micboost)
+    }
+
+    fn reduce(&mut self, steps: u8) {
+        match self.input {
+            InselV::Line => {
+                let raw = self.gain.into_raw().saturating_sub(steps);
+                self.gain = InVoldB::from_raw(raw.max(self.config.min_gain.into_raw()));
+            }
+            InselV::Mic => {
+                if self.config.micboost {
+                    self.micboost = false;
+                }
+            }
+        }
+    }
+
+    fn raise(&mut self, margin: i32) {
+        match self.input {
+            InselV::Line => {
+                if self.gain.into_raw() < self.config.max_gain.into_raw() {
+                    Gain::increase(&mut self.gain);
+                }
+            }
+            InselV::Mic => {
+                if self.config.micboost && margin + HYSTERESIS_CB as i32 >= MICBOOST_CB as i32 {
+                    self.micboost = true;
+                }
+            }
+        }
+    }
+
+    /// Write the decisions to the codec, only where they differ.
+    pub fn apply<I: WriteFrame>(&self, codec: &mut Wm8731<I>) {
+        match self.input {
+            InselV::Line => {
+                if codec.both_invol() != (self.gain, self.gain) {
+                    codec.set_both_invol(self.gain);
+                }
+            }
+            InselV::Mic => {
+                if self.config.micboost && codec.micboost() != self.micboost {
+                    codec.set_micboost(self.micboost);
+                }
+            }
+        }
+    }
+
+    /// Take input selection, gain and `MICBOOST` from the codec, so manual changes are not
+    /// overridden.
+    pub fn sync<I: WriteFrame>(&mut self, codec: &Wm8731<I>) {
+        self.input = codec.insel();
+        self.gain = codec.left_invol();
+        self.micboost = codec.micboost();
+    }
+
+    /// Synchronise with the codec, process a block and write the decisions.
+    pub fn update<I: WriteFrame>(&mut self, codec: &mut Wm8731<I>, samples: &[i32]) {
+        self.sync(codec);
+        if self.process(samples) {
+            self.apply(codec);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 48_000;
+
+    /// 10ms block of a square wave at `level` dBFS, after `gain`.
+    fn block(level: Centibels, gain: i16, buf: &mut [i32; 960]) {
+        let full_scale = IwlV::Iwl16Bits.full_scale() as u64;
+        let amplitude =
+            (full_scale * math::centibels_to_ratio_q16((level.0 + gain) as i32) as u64) >> 16;
+        let amplitude = amplitude.min(full_scale - 1) as i32;
+        for (i, frame) in buf.chunks_mut(2).enumerate() {
+            let sample = if i % 24 < 12 { amplitude } else { -amplitude };
+            frame[0] = sample;
+            frame[1] = sample;
+        }
+    }
+
+    fn run(agc: &mut Agc, level: Centibels, blocks: usize) {
+        let mut buf = [0; 960];
+        for _ in 0..blocks {
+            block(level, agc.gain().to_centibels().0, &mut buf);
+            agc.process(&buf);
+        }
+    }
+
+    #[test]
+    fn line_test() {
+        let mut agc = Agc::new(AgcConfig::DEFAULT, RATE, IwlV::Iwl16Bits);
+        // loud signal, gain is reduced quickly
+        run(&mut agc, Centibels(-20), 5);
+        assert_eq!(agc.gain(), InVoldB::N10DB5);
+        // quieter signal, gain increase only after hold
+        run(&mut agc, Centibels(-200), 45);
+        assert_eq!(agc.gain(), InVoldB::N10DB5);
+        run(&mut agc, Centibels(-200), 150);
+        assert_eq!(agc.gain(), InVoldB::P7DB5);
+        assert!((agc.peak_dbfs().0 + 120).abs() <= 10);
+        // noise is not amplified
+        let gain = agc.gain();
+        run(&mut agc, Centibels(-800), 200);
+        assert_eq!(agc.gain(), gain);
+    }
+
+    #[test]
+    fn mic_test() {
+        let config = AgcConfig {
+            micboost: true,
+            ..AgcConfig::DEFAULT
+        };
+        let mut agc = Agc::new(config, RATE, IwlV::Iwl16Bits);
+        agc.set_input(InselV::Mic);
+        let mut buf = [0; 960];
+        block(Centibels(-400), 0, &mut buf);
+        for _ in 0..100 {
+            agc.process(&buf);
+        }
+        assert!(agc.micboost());
+        block(Centibels(-30), 0, &mut buf);
+        assert!(agc.process(&buf));
+        assert!(!agc.micboost());
+    }
+}
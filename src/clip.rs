@@ -0,0 +1,316 @@
+//! Input clipping protection.
+//!
+//! [`ClipGuard`] watch ADC samples for full scale clipping and immediately lower the line input
+//! volume of the clipped channel by a fixed number of steps. Each clip is recorded as a
+//! [`ClipEvent`], and the gain in use before the first backoff is kept so it can be restored
+//! later with [`ClipGuard::restore`].
+//!
+//! When the microphone is selected, line input volume has no effect, so `MICBOOST` is disabled
+//! instead.
+use crate::db::Centibels;
+use crate::interface::WriteFrame;
+use crate::math;
+use crate::{InVoldB, InselV, IwlV, Wm8731};
+
+/// Number of events kept in the log.
+pub const CLIP_LOG_CAPACITY: usize = 8;
+
+/// Clip guard parameters.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct ClipGuardConfig {
+    /// Level, in dBFS, at which a sample is considered clipped.
+    pub threshold: Centibels,
+    /// Number of 1.5dB steps removed on each clip.
+    pub backoff_steps: u8,
+    /// Gain is never reduced below this.
+    pub min_gain: InVoldB,
+    /// Minimum time between two backoffs, so samples captured before a backoff don't trigger
+    /// another one.
+    pub holdoff_ms: u32,
+}
+
+impl ClipGuardConfig {
+    /// Clip at full scale, 3dB backoff.
+    pub const DEFAULT: ClipGuardConfig = ClipGuardConfig {
+        threshold: Centibels::ZERO,
+        backoff_steps: 2,
+        min_gain: InVoldB::MIN,
+        holdoff_ms: 50,
+    };
+}
+
+impl Default for ClipGuardConfig {
+    fn default() -> Self {
+        ClipGuardConfig::DEFAULT
+    }
+}
+
+/// What was done about a clip.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum ClipAction {
+    /// Line input volumes lowered to these values.
+    GainReduced { left: InVoldB, right: InVoldB },
+    /// `MICBOOST` disabled.
+    MicboostDisabled,
+    /// Nothing could be done, gain is already at minimum.
+    AtMinimum,
+    /// Nothing done because of the hold off time.
+    HeldOff,
+}
+
+/// A clip occurrence.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct ClipEvent {
+    pub time_ms: u32,
+    pub left: bool,
+    pub right: bool,
+    /// Greatest sample magnitude of the block.
+    pub peak: u32,
+    pub action: ClipAction,
+}
+
+/// Clip detector with automatic gain backoff.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct ClipGuard {
+    config: ClipGuardConfig,
+    threshold: u32,
+    /// Volumes and `MICBOOST` before the first backoff.
+    original: Option<(InVoldB, InVoldB, bool)>,
+    last_backoff_ms: Option<u32>,
+    log: [Option<ClipEvent>; CLIP_LOG_CAPACITY],
+    next: usize,
+    count: u32,
+}
+
+impl ClipGuard {
+    pub fn new(config: ClipGuardConfig, iwl: IwlV) -> Self {
+        let mut guard = Self {
+            config,
+            threshold: 0,
+            original: None,
+            last_backoff_ms: None,
+            log: [None; CLIP_LOG_CAPACITY],
+            next: 0,
+            count: 0,
+        };
+        guard.set_iwl(iwl);
+        guard
+    }
+
+    pub fn config(&self) -> ClipGuardConfig {
+        self.config
+    }
+
+    /// Update the sample word length, it must follow the interface configuration.
+    pub fn set_iwl(&mut self, iwl: IwlV) {
+        let full_scale = iwl.full_scale() as u64;
+        let ratio = math::centibels_to_ratio_q16(self.config.threshold.0 as i32) as u64;
+        // a positive sample can't exceed full scale - 1
+        self.threshold = ((full_scale * ratio) >> 16).min(full_scale - 1) as u32;
+    }
+
+    /// Sample magnitude considered as clipping.
+    pub fn threshold(&self) -> u32 {
+        self.threshold
+    }
+
+    /// Detect clipping on each channel of a block of interleaved stereo samples.
+    pub fn detect(&self, samples: &[i32]) -> (bool, bool) {
+        let clipped = |channel: usize| {
+            samples
+                .iter()
+                .skip(channel)
+                .step_by(2)
+                .any(|s| s.unsigned_abs() >= self.threshold)
+        };
+        (clipped(0), clipped(1))
+    }
+
+    /// Check a block of interleaved stereo samples and back off gain if it clipped. Return the
+    /// event if a clip was detected.
+    pub fn process<I>(
+        &mut self,
+        codec: &mut Wm8731<I>,
+        samples: &[i32],
+        now_ms: u32,
+    ) -> Option<ClipEvent>
+    where
+        I: WriteFrame,
+    {
+        let (left, right) = self.detect(samples);
+        if !left && !right {
+            return None;
+        }
+        let held_off = match self.last_backoff_ms {
+            Some(last) => now_ms.wrapping_sub(last) < self.config.holdoff_ms,
+            None => false,
+        };
+        let action = if held_off {
+            ClipAction::HeldOff
+        } else {
+            self.back_off(codec, left, right)
+        };
+        if action != ClipAction::HeldOff && action != ClipAction::AtMinimum {
+            self.last_backoff_ms = Some(now_ms);
+        }
+        let event = ClipEvent {
+            time_ms: now_ms,
+            left,
+            right,
+            peak: math::peak(samples),
+            action,
+        };
+        self.log[self.next] = Some(event);
+        self.next = (self.next + 1) % CLIP_LOG_CAPACITY;
+        self.count = self.count.wrapping_add(1);
+        Some(event)
+    }
+
+    fn back_off<I>(&mut self, codec: &mut Wm8731<I>, left: bool, right: bool) -> ClipAction
+    where
+        I: WriteFrame,
+    {
+        let (left_vol, right_vol) = codec.both_invol();
+        if self.original.is_none() {
+            self.original = Some((left_vol, right_vol, codec.micboost()));
+        }
+        if codec.insel() == InselV::Mic {
+            if codec.micboost() {
+                codec.set_micboost(false);
+                return ClipAction::MicboostDisabled;
+            }
+            return ClipAction::AtMinimum;
+        }
+        let min = self.config.min_gain.into_raw();
+        let lower = |vol: InVoldB, clipped: bool| {
+            if clipped && vol.into_raw() > min {
+                InVoldB::from_raw(
+                    vol.into_raw()
+                        .saturating_sub(self.config.backoff_steps)
+                        .max(min),
+                )
+            } else {
+                vol
+            }
+        };
+        let (new_left, new_right) = (lower(left_vol, left), lower(right_vol, right));
+        if (new_left, new_right) == (left_vol, right_vol) {
+            return ClipAction::AtMinimum;
+        }
+        if new_left == new_right {
+            codec.set_both_invol(new_left);
+        } else {
+            if new_left != left_vol {
+                codec.set_left_invol(new_left);
+            }
+            if new_right != right_vol {
+                codec.set_right_invol(new_right);
+            }
+        }
+        ClipAction::GainReduced {
+            left: new_left,
+            right: new_right,
+        }
+    }
+
+    /// Returns `true` if gain was reduced since creation or last [`ClipGuard::restore`].
+    pub fn backed_off(&self) -> bool {
+        self.original.is_some()
+    }
+
+    /// Volumes and `MICBOOST` in use before the first backoff.
+    pub fn original_gain(&self) -> Option<(InVoldB, InVoldB, bool)> {
+        self.original
+    }
+
+    /// Raise volumes and `MICBOOST` back to their value before the first backoff. Return `false`
+    /// if there was nothing to restore.
+    pub fn restore<I>(&mut self, codec: &mut Wm8731<I>) -> bool
+    where
+        I: WriteFrame,
+    {
+        let (left, right, micboost) = match self.original.take() {
+            Some(original) => original,
+            None => return false,
+        };
+        if left == right {
+            codec.set_both_invol(left);
+        } else {
+            codec.set_left_invol(left);
+            codec.set_right_invol(right);
+        }
+        if micboost != codec.micboost() {
+            codec.set_micboost(micboost);
+        }
+        self.last_backoff_ms = None;
+        true
+    }
+
+    /// Number of clips detected, including those no longer in the log.
+    pub fn clip_count(&self) -> u32 {
+        self.count
+    }
+
+    /// Logged events, oldest first.
+    pub fn events(&self) -> impl Iterator<Item = ClipEvent> + '_ {
+        let (recent, old) = self.log.split_at(self.next);
+        old.iter().chain(recent.iter()).flatten().copied()
+    }
+
+    pub fn clear_events(&mut self) {
+        self.log = [None; CLIP_LOG_CAPACITY];
+        self.next = 0;
+        self.count = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interface::mock::FrameLog;
+
+    #[test]
+    fn backoff_test() {
+        let mut codec = Wm8731::new(FrameLog::new());
+        codec.set_both_invol(InVoldB::MAX);
+        let mut guard = ClipGuard::new(ClipGuardConfig::DEFAULT, IwlV::Iwl16Bits);
+        assert_eq!(guard.threshold(), 32767);
+        assert!(guard
+            .process(&mut codec, &[100, -200, 300, 400], 0)
+            .is_none());
+        let event = guard.process(&mut codec, &[100, -32768, 0, 0], 10).unwrap();
+        assert!(!event.left && event.right);
+        assert_eq!(codec.both_invol(), (InVoldB::MAX, InVoldB::P9DB));
+        // within hold off, logged but nothing done
+        let event = guard.process(&mut codec, &[32767, 32767], 20).unwrap();
+        assert_eq!(event.action, ClipAction::HeldOff);
+        let event = guard.process(&mut codec, &[32767, 32767], 100).unwrap();
+        assert_eq!(
+            event.action,
+            ClipAction::GainReduced {
+                left: InVoldB::P9DB,
+                right: InVoldB::P6DB
+            }
+        );
+        assert_eq!(guard.clip_count(), 3);
+        assert_eq!(guard.events().count(), 3);
+        assert!(guard.restore(&mut codec));
+        assert_eq!(codec.both_invol(), (InVoldB::MAX, InVoldB::MAX));
+        assert!(!guard.restore(&mut codec));
+    }
+
+    #[test]
+    fn log_test() {
+        let mut codec = Wm8731::new(FrameLog::new());
+        let mut guard = ClipGuard::new(ClipGuardConfig::DEFAULT, IwlV::Iwl24Bits);
+        for i in 0..10 {
+            guard.process(&mut codec, &[1 << 23, 0], i * 100);
+        }
+        assert_eq!(guard.clip_count(), 10);
+        let mut events = guard.events();
+        assert_eq!(events.next().map(|e| e.time_ms), Some(200));
+        assert_eq!(events.last().map(|e| e.time_ms), Some(900));
+        guard.clear_events();
+        assert_eq!(guard.events().count(), 0);
+    }
+}
@@ -0,0 +1,106 @@
+//! Clock source modelling.
+//!
+//! The codec core clock come either from a crystal connected between XTI/MCLK and XTO, or from an
+//! external master clock driven on XTI/MCLK. It can be divided by 2 (`CLKIDIV2`), and can be
+//! output on CLKOUT, eventually divided by 2 (`CLKODIV2`).
+
+/// Where the codec master clock come from.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum ClockSource {
+    /// Crystal connected between XTI/MCLK and XTO. The on-chip oscillator is powered.
+    Crystal { hz: u32 },
+    /// Clock driven on XTI/MCLK by an external device. The on-chip oscillator is powered down.
+    ExternalMclk { hz: u32 },
+}
+
+impl ClockSource {
+    /// Frequency of the master clock.
+    pub const fn hz(&self) -> u32 {
+        match *self {
+            ClockSource::Crystal { hz } => hz,
+            ClockSource::ExternalMclk { hz } => hz,
+        }
+    }
+}
+
+/// CLKOUT divider.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum ClkoutDiv {
+    /// CLKOUT is the core clock.
+    Div1,
+    /// CLKOUT is the core clock divided by 2.
+    Div2,
+}
+
+/// Clock configuration, gathering `OSCPD`, `CLKOUTPD`, `CLKIDIV2` and `CLKODIV2` fields meaning.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct ClockConfig {
+    pub source: ClockSource,
+    /// Divide master clock by 2 to generate the core clock.
+    pub core_div2: bool,
+    /// CLKOUT divider, `None` when CLKOUT is powered down.
+    pub clkout: Option<ClkoutDiv>,
+}
+
+impl ClockConfig {
+    /// Core clock is master clock, CLKOUT is powered down.
+    pub const fn new(source: ClockSource) -> Self {
+        Self {
+            source,
+            core_div2: false,
+            clkout: None,
+        }
+    }
+
+    pub const fn with_core_div2(self, value: bool) -> Self {
+        Self {
+            core_div2: value,
+            ..self
+        }
+    }
+
+    pub const fn with_clkout(self, value: Option<ClkoutDiv>) -> Self {
+        Self {
+            clkout: value,
+            ..self
+        }
+    }
+
+    /// Value of the `OSCPD` field.
+    pub const fn oscpd(&self) -> bool {
+        matches!(self.source, ClockSource::ExternalMclk { .. })
+    }
+
+    /// Value of the `CLKOUTPD` field.
+    pub const fn clkoutpd(&self) -> bool {
+        self.clkout.is_none()
+    }
+
+    /// Value of the `CLKIDIV2` field.
+    pub const fn clkidiv2(&self) -> bool {
+        self.core_div2
+    }
+
+    /// Value of the `CLKODIV2` field.
+    pub const fn clkodiv2(&self) -> bool {
+        matches!(self.clkout, Some(ClkoutDiv::Div2))
+    }
+
+    /// Frequency of the core clock, used to generate sampling frequencies.
+    pub const fn core_clock_hz(&self) -> u32 {
+        if self.core_div2 {
+            self.source.hz() / 2
+        } else {
+            self.source.hz()
+        }
+    }
+
+    /// Frequency of CLKOUT, `None` when CLKOUT is powered down.
+    pub const fn clkout_hz(&self) -> Option<u32> {
+        match self.clkout {
+            Some(ClkoutDiv::Div1) => Some(self.core_clock_hz()),
+            Some(ClkoutDiv::Div2) => Some(self.core_clock_hz() / 2),
+            None => None,
+        }
+    }
+}
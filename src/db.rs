@@ -0,0 +1,210 @@
+//! Fixed point dB arithmetic.
+//!
+//! Gains are represented in centibels (1/10 dB) so the 1.5 dB steps of [`InVoldB`](crate::InVoldB)
+//! are represented exactly. This module provide the [`Centibels`] type, rounding policies and
+//! errors used by conversions of [`HpVoldB`](crate::HpVoldB), [`InVoldB`](crate::InVoldB) and
+//! [`SideAttdB`](crate::SideAttdB).
+use crate::math;
+use core::fmt;
+use core::ops::{Add, Neg, Sub};
+
+/// A gain in centibels, i.e. tenths of dB.
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Clone, Copy, Default)]
+pub struct Centibels(pub i16);
+
+impl Centibels {
+    pub const ZERO: Centibels = Centibels(0);
+
+    /// Build from an integer number of dB. Saturate on overflow.
+    pub const fn from_db(db: i16) -> Self {
+        Self(db.saturating_mul(10))
+    }
+
+    /// Return the number of whole dB, rounded toward zero.
+    pub const fn db(self) -> i16 {
+        self.0 / 10
+    }
+
+    /// Gain of an amplitude ratio `num / den`, saturated to `i16` range. A null `num` gives
+    /// `Centibels(i16::MIN)`.
+    ///
+    /// With `den` being a full scale value, this gives a level in dBFS.
+    pub fn from_ratio(num: u32, den: u32) -> Self {
+        let cb = math::ratio_to_centibels(num, den);
+        Self(cb.clamp(i16::MIN as i32, i16::MAX as i32) as i16)
+    }
+}
+
+impl Add for Centibels {
+    type Output = Centibels;
+    /// Saturating addition.
+    fn add(self, rhs: Centibels) -> Centibels {
+        Centibels(self.0.saturating_add(rhs.0))
+    }
+}
+
+impl Sub for Centibels {
+    type Output = Centibels;
+    /// Saturating subtraction.
+    fn sub(self, rhs: Centibels) -> Centibels {
+        Centibels(self.0.saturating_sub(rhs.0))
+    }
+}
+
+impl Neg for Centibels {
+    type Output = Centibels;
+    fn neg(self) -> Centibels {
+        Centibels(self.0.saturating_neg())
+    }
+}
+
+impl fmt::Display for Centibels {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "+" };
+        let abs = self.0.unsigned_abs();
+        if abs % 10 == 0 {
+            write!(f, "{}{}dB", sign, abs / 10)
+        } else {
+            write!(f, "{}{}.{}dB", sign, abs / 10, abs % 10)
+        }
+    }
+}
+
+/// How to choose a step when a gain fall between two steps.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Rounding {
+    /// Closest step, higher gain on tie.
+    Nearest,
+    /// Closest step with a greater or equal gain.
+    Up,
+    /// Closest step with a lower or equal gain.
+    Down,
+}
+
+///Error returned when converting a dB value into a gain type.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum DbError {
+    ///The string is not a valid dB value.
+    Syntax,
+    ///The value is outside the range of the gain type.
+    OutOfRange,
+    ///The value is in range but doesn't match a step of the gain type.
+    NotRepresentable,
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbError::Syntax => f.write_str("invalid dB value"),
+            DbError::OutOfRange => f.write_str("dB value out of range"),
+            DbError::NotRepresentable => f.write_str("dB value doesn't match a step"),
+        }
+    }
+}
+
+/// Index of the step matching `cb` on a regular scale starting at `min` with `step` spacing,
+/// saturated to `0..=max_index`.
+pub(crate) fn step_index(
+    cb: Centibels,
+    min: Centibels,
+    step: i16,
+    max_index: u8,
+    rounding: Rounding,
+) -> u8 {
+    let offset = cb.0 as i32 - min.0 as i32;
+    let step = step as i32;
+    let index = match rounding {
+        Rounding::Nearest => (offset + step / 2).div_euclid(step),
+        Rounding::Up => (offset + step - 1).div_euclid(step),
+        Rounding::Down => offset.div_euclid(step),
+    };
+    index.clamp(0, max_index as i32) as u8
+}
+
+/// Index of the step exactly matching `cb`.
+pub(crate) fn exact_step_index(
+    cb: Centibels,
+    min: Centibels,
+    step: i16,
+    max_index: u8,
+) -> Result<u8, DbError> {
+    let offset = cb.0 as i32 - min.0 as i32;
+    if offset < 0 || offset > max_index as i32 * step as i32 {
+        Err(DbError::OutOfRange)
+    } else if offset % step as i32 != 0 {
+        Err(DbError::NotRepresentable)
+    } else {
+        Ok((offset / step as i32) as u8)
+    }
+}
+
+/// Parse strings like `-12dB`, `+1.5 dB`, `3` or `mute`. Return `None` for mute.
+///
+/// Only one decimal is accepted, since no gain type has a finer resolution.
+pub(crate) fn parse(s: &str) -> Result<Option<Centibels>, DbError> {
+    let s = s.trim();
+    if s.eq_ignore_ascii_case("mute") {
+        return Ok(None);
+    }
+    let s = match s.len().checked_sub(2) {
+        Some(end) if s.is_char_boundary(end) && s[end..].eq_ignore_ascii_case("db") => {
+            s[..end].trim_end()
+        }
+        _ => s,
+    };
+    let (negative, s) = match s.as_bytes().first() {
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        _ => (false, s),
+    };
+    let (int, frac) = match s.split_once('.') {
+        Some((int, frac)) => (int, frac),
+        None => (s, ""),
+    };
+    let all_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    if int.is_empty() || !all_digits(int) || !all_digits(frac) || frac.len() > 1 {
+        return Err(DbError::Syntax);
+    }
+    let int: i16 = int.parse().map_err(|_| DbError::OutOfRange)?;
+    let frac: i16 = if frac.is_empty() {
+        0
+    } else {
+        frac.parse().map_err(|_| DbError::Syntax)?
+    };
+    let cb = int
+        .checked_mul(10)
+        .and_then(|cb| cb.checked_add(frac))
+        .ok_or(DbError::OutOfRange)?;
+    Ok(Some(Centibels(if negative { -cb } else { cb })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn parse_test() {
+        assert_eq!(parse("-12dB"), Ok(Some(Centibels(-120))));
+        assert_eq!(parse(" +1.5 dB "), Ok(Some(Centibels(15))));
+        assert_eq!(parse("3"), Ok(Some(Centibels(30))));
+        assert_eq!(parse("MUTE"), Ok(None));
+        assert_eq!(parse("-1.25dB"), Err(DbError::Syntax));
+        assert_eq!(parse("dB"), Err(DbError::Syntax));
+        assert_eq!(parse("-x2dB"), Err(DbError::Syntax));
+    }
+    #[test]
+    fn step_index_test() {
+        let min = Centibels(-345);
+        assert_eq!(
+            step_index(Centibels(-120), min, 15, 31, Rounding::Nearest),
+            15
+        );
+        assert_eq!(
+            step_index(Centibels(-125), min, 15, 31, Rounding::Nearest),
+            15
+        );
+        assert_eq!(step_index(Centibels(-125), min, 15, 31, Rounding::Down), 14);
+        assert_eq!(step_index(Centibels(-131), min, 15, 31, Rounding::Up), 15);
+        assert_eq!(step_index(Centibels(-1000), min, 15, 31, Rounding::Up), 0);
+        assert_eq!(step_index(Centibels(1000), min, 15, 31, Rounding::Down), 31);
+    }
+}
@@ -0,0 +1,138 @@
+//! Configuration consistency diagnostics.
+//!
+//! Many configurations are accepted by the codec but produce silence or misbehave, for example
+//! a path routed through a powered down block. [`Wm8731::diagnose`](crate::Wm8731::diagnose)
+//! inspect the configuration stored in the driver and report such issues as [`Warning`]s.
+use core::fmt;
+
+/// A configuration issue.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Warning {
+    /// `DACSEL` is set but `DACPD` is set.
+    DacRoutedButPoweredDown,
+    /// `DACSEL` is set, the interface is active but `DACMU` is set.
+    DacRoutedButMuted,
+    /// `INSEL` select line in, ADC is powered but `LINEINPD` is set.
+    LineInSelectedButPoweredDown,
+    /// `INSEL` select line in, ADC is powered but both line inputs are muted.
+    LineInSelectedButMuted,
+    /// `INSEL` select microphone, ADC is powered but `MICPD` is set.
+    MicSelectedButPoweredDown,
+    /// `INSEL` select microphone, ADC is powered but `MUTEMIC` is set.
+    MicSelectedButMuted,
+    /// `BYPASS` is set but `LINEINPD` is set.
+    BypassButLineInPoweredDown,
+    /// `SIDETONE` is set but `MICPD` is set.
+    SidetoneButMicPoweredDown,
+    /// Something is routed to outputs, but `OUTPD` is set.
+    RoutedToPoweredDownOutputs,
+    /// De-emphasis is enabled but doesn't match the DAC sampling frequency.
+    DeemphasisRateMismatch,
+    /// DAC is powered and soft mute doesn't work with the sampling configuration.
+    SoftMuteUnsupported,
+    /// Outputs are powered with DAC routed to them, while the interface is inactive.
+    OutputsPoweredWhileInactive,
+    /// Digital Audio Interface Format fields contain a forbidden combination.
+    InvalidAudioFormat,
+}
+
+impl Warning {
+    /// Number of different warnings.
+    pub const COUNT: usize = 13;
+
+    /// Explain the issue and its consequence.
+    pub const fn explanation(&self) -> &'static str {
+        match self {
+            Warning::DacRoutedButPoweredDown => {
+                "DAC is routed to outputs but powered down, playback is silent"
+            }
+            Warning::DacRoutedButMuted => "DAC is routed to outputs but muted, playback is silent",
+            Warning::LineInSelectedButPoweredDown => {
+                "line in is selected as ADC input but powered down, recording is silent"
+            }
+            Warning::LineInSelectedButMuted => {
+                "line in is selected as ADC input but muted, recording is silent"
+            }
+            Warning::MicSelectedButPoweredDown => {
+                "microphone is selected as ADC input but powered down, recording is silent"
+            }
+            Warning::MicSelectedButMuted => {
+                "microphone is selected as ADC input but muted, recording is silent"
+            }
+            Warning::BypassButLineInPoweredDown => {
+                "bypass is enabled but line in is powered down, bypass is silent"
+            }
+            Warning::SidetoneButMicPoweredDown => {
+                "sidetone is enabled but microphone is powered down, sidetone is silent"
+            }
+            Warning::RoutedToPoweredDownOutputs => {
+                "a path is routed to outputs but outputs are powered down, they are silent"
+            }
+            Warning::DeemphasisRateMismatch => {
+                "de-emphasis filter doesn't match DAC sampling frequency, response is wrong"
+            }
+            Warning::SoftMuteUnsupported => {
+                "DAC soft mute doesn't work correctly with the sampling configuration"
+            }
+            Warning::OutputsPoweredWhileInactive => {
+                "outputs are powered while the interface is inactive, expect pops on activation"
+            }
+            Warning::InvalidAudioFormat => {
+                "digital audio interface format is forbidden, audio data are corrupted"
+            }
+        }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.explanation())
+    }
+}
+
+/// List of warnings, without allocation.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct Diagnostics {
+    warnings: [Option<Warning>; Warning::COUNT],
+    len: usize,
+}
+
+impl Default for Diagnostics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Diagnostics {
+    /// Empty list.
+    pub const fn new() -> Self {
+        Self {
+            warnings: [None; Warning::COUNT],
+            len: 0,
+        }
+    }
+
+    /// Add a warning, unless it is already present.
+    pub fn push(&mut self, warning: Warning) {
+        if !self.contains(warning) && self.len < Warning::COUNT {
+            self.warnings[self.len] = Some(warning);
+            self.len += 1;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn contains(&self, warning: Warning) -> bool {
+        self.iter().any(|w| w == warning)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Warning> + '_ {
+        self.warnings[..self.len].iter().flatten().copied()
+    }
+}
@@ -0,0 +1,381 @@
+//! Non-blocking volume fades.
+//!
+//! Changing a volume in big jumps produces zipper noise, even with zero cross detection.
+//! [`FadeEngine`] move headphone and line in volumes progressively to a target during a given
+//! duration. It doesn't block: it is advanced by calling [`FadeEngine::tick`], typically from a
+//! timer, and send at most one frame per tick. A frame change a volume by at most
+//! [`MAX_STEP`] codes, so a late tick doesn't jump: the fade catches up over the next ticks.
+//!
+//! Times are in milliseconds, from any free running wrapping counter.
+use crate::interface::WriteFrame;
+use crate::{HpVoldB, InVoldB, Wm8731};
+
+/// Largest volume change sent by a tick, in register codes: 3dB for headphones and 4.5dB for line
+/// inputs.
+pub const MAX_STEP: u8 = 3;
+
+/// Gain stage that can be faded.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum GainStage {
+    LeftHeadphone,
+    RightHeadphone,
+    LeftLineIn,
+    RightLineIn,
+}
+
+impl GainStage {
+    const ALL: [GainStage; 4] = [
+        GainStage::LeftHeadphone,
+        GainStage::RightHeadphone,
+        GainStage::LeftLineIn,
+        GainStage::RightLineIn,
+    ];
+
+    const fn index(self) -> usize {
+        self as usize
+    }
+
+    fn current_raw<I: WriteFrame>(self, codec: &Wm8731<I>) -> u8 {
+        match self {
+            GainStage::LeftHeadphone => codec.left_hpvol().into_raw(),
+            GainStage::RightHeadphone => codec.right_hpvol().into_raw(),
+            GainStage::LeftLineIn => codec.left_invol().into_raw(),
+            GainStage::RightLineIn => codec.right_invol().into_raw(),
+        }
+    }
+
+    /// Lower a headphone `raw` value to the codec volume limit.
+    fn limit_raw<I: WriteFrame>(self, codec: &Wm8731<I>, raw: u8) -> u8 {
+        match (self, codec.hpvol_cap()) {
+            (GainStage::LeftHeadphone | GainStage::RightHeadphone, Some(cap)) => {
+                raw.min(cap.into_raw())
+            }
+            _ => raw,
+        }
+    }
+}
+
+/// Target of a fade: a gain stage and its final volume.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum FadeTarget {
+    LeftHeadphone(HpVoldB),
+    RightHeadphone(HpVoldB),
+    LeftLineIn(InVoldB),
+    RightLineIn(InVoldB),
+}
+
+impl FadeTarget {
+    pub const fn stage(&self) -> GainStage {
+        match self {
+            FadeTarget::LeftHeadphone(_) => GainStage::LeftHeadphone,
+            FadeTarget::RightHeadphone(_) => GainStage::RightHeadphone,
+            FadeTarget::LeftLineIn(_) => GainStage::LeftLineIn,
+            FadeTarget::RightLineIn(_) => GainStage::RightLineIn,
+        }
+    }
+
+    const fn raw(&self) -> u8 {
+        match self {
+            FadeTarget::LeftHeadphone(vol) | FadeTarget::RightHeadphone(vol) => vol.into_raw(),
+            FadeTarget::LeftLineIn(vol) | FadeTarget::RightLineIn(vol) => vol.into_raw(),
+        }
+    }
+}
+
+/// State of a fade on a gain stage.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum FadeStatus {
+    /// No fade was started, or it was cancelled.
+    Idle,
+    /// Fade in progress.
+    Running,
+    /// Target reached.
+    Completed,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+struct Fade {
+    status: FadeStatus,
+    start_raw: u8,
+    target_raw: u8,
+    /// Last value sent.
+    current_raw: u8,
+    start_ms: u32,
+    duration_ms: u32,
+}
+
+impl Fade {
+    const IDLE: Fade = Fade {
+        status: FadeStatus::Idle,
+        start_raw: 0,
+        target_raw: 0,
+        current_raw: 0,
+        start_ms: 0,
+        duration_ms: 0,
+    };
+
+    /// Value the fade should have at `now`.
+    fn expected_raw(&self, now: u32) -> u8 {
+        let elapsed = now.wrapping_sub(self.start_ms);
+        if elapsed >= self.duration_ms {
+            return self.target_raw;
+        }
+        let span = self.target_raw as i64 - self.start_raw as i64;
+        let step = span * elapsed as i64 / self.duration_ms as i64;
+        (self.start_raw as i64 + step) as u8
+    }
+
+    /// Value to send at `now`, at most [`MAX_STEP`] away from the last value sent.
+    fn next_raw(&self, now: u32) -> u8 {
+        let expected = self.expected_raw(now);
+        if expected > self.current_raw {
+            expected.min(self.current_raw.saturating_add(MAX_STEP))
+        } else {
+            expected.max(self.current_raw.saturating_sub(MAX_STEP))
+        }
+    }
+}
+
+/// Fade engine for headphone and line in volumes.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct FadeEngine {
+    fades: [Fade; 4],
+    zcen: bool,
+    next: usize,
+}
+
+impl Default for FadeEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FadeEngine {
+    /// Engine without any running fade. Headphone volumes are changed without zero cross
+    /// detection.
+    pub const fn new() -> Self {
+        Self {
+            fades: [Fade::IDLE; 4],
+            zcen: false,
+            next: 0,
+        }
+    }
+
+    /// Use zero cross detection for headphone volume changes.
+    pub const fn with_zcen(self, zcen: bool) -> Self {
+        Self { zcen, ..self }
+    }
+
+    /// Start a fade from the current volume to `target`, lasting `duration_ms`. Headphone targets
+    /// are lowered to the codec volume limit, during the whole fade.
+    ///
+    /// If a fade is already running on the same gain stage, it is retargeted: the new fade start
+    /// from the volume reached so far.
+    pub fn start<I>(&mut self, codec: &Wm8731<I>, target: FadeTarget, duration_ms: u32, now: u32)
+    where
+        I: WriteFrame,
+    {
+        let stage = target.stage();
+        let fade = &mut self.fades[stage.index()];
+        let start_raw = match fade.status {
+            FadeStatus::Running => fade.current_raw,
+            _ => stage.current_raw(codec),
+        };
+        *fade = Fade {
+            status: FadeStatus::Running,
+            start_raw,
+            target_raw: stage.limit_raw(codec, target.raw()),
+            current_raw: start_raw,
+            start_ms: now,
+            duration_ms,
+        };
+    }
+
+    /// Stop a fade, leaving the volume where it is.
+    pub fn cancel(&mut self, stage: GainStage) {
+        self.fades[stage.index()].status = FadeStatus::Idle;
+    }
+
+    /// Stop every fade.
+    pub fn cancel_all(&mut self) {
+        for fade in self.fades.iter_mut() {
+            fade.status = FadeStatus::Idle;
+        }
+    }
+
+    pub fn status(&self, stage: GainStage) -> FadeStatus {
+        self.fades[stage.index()].status
+    }
+
+    /// Returns `true` if no fade is running.
+    pub fn is_idle(&self) -> bool {
+        self.fades.iter().all(|f| f.status != FadeStatus::Running)
+    }
+
+    /// Advance fades, sending at most one frame. Return `true` if a frame was sent.
+    ///
+    /// Gain stages are served in turn. When both headphone volumes must be set to the same value,
+    /// they are set with a single frame.
+    pub fn tick<I>(&mut self, codec: &mut Wm8731<I>, now: u32) -> bool
+    where
+        I: WriteFrame,
+    {
+        for offset in 0..GainStage::ALL.len() {
+            let index = (self.next + offset) % GainStage::ALL.len();
+            let stage = GainStage::ALL[index];
+            if self.fades[index].status != FadeStatus::Running {
+                continue;
+            }
+            // the codec lowers volumes when its limit is lowered
+            let fade = &mut self.fades[index];
+            fade.target_raw = stage.limit_raw(codec, fade.target_raw);
+            fade.current_raw = stage.limit_raw(codec, fade.current_raw);
+            let fade = self.fades[index];
+            let raw = stage.limit_raw(codec, fade.next_raw(now));
+            if raw == fade.target_raw {
+                self.fades[index].status = FadeStatus::Completed;
+            }
+            if raw == fade.current_raw {
+                continue;
+            }
+            self.fades[index].current_raw = raw;
+            self.write(codec, stage, raw, now);
+            self.next = (index + 1) % GainStage::ALL.len();
+            return true;
+        }
+        false
+    }
+
+    fn write<I>(&mut self, codec: &mut Wm8731<I>, stage: GainStage, raw: u8, now: u32)
+    where
+        I: WriteFrame,
+    {
+        match stage {
+            GainStage::LeftHeadphone | GainStage::RightHeadphone => {
+                let other = match stage {
+                    GainStage::LeftHeadphone => GainStage::RightHeadphone,
+                    _ => GainStage::LeftHeadphone,
+                };
+                let other_fade = &mut self.fades[other.index()];
+                other_fade.target_raw = other.limit_raw(codec, other_fade.target_raw);
+                other_fade.current_raw = other.limit_raw(codec, other_fade.current_raw);
+                let other_raw = other.limit_raw(codec, other_fade.next_raw(now));
+                let vol = HpVoldB::from_raw(raw);
+                if other_fade.status == FadeStatus::Running && other_raw == raw {
+                    other_fade.current_raw = raw;
+                    if raw == other_fade.target_raw {
+                        other_fade.status = FadeStatus::Completed;
+                    }
+                    codec.set_both_hpvol(vol, self.zcen);
+                } else if stage == GainStage::LeftHeadphone {
+                    codec.set_left_hpvol(vol, self.zcen);
+                } else {
+                    codec.set_right_hpvol(vol, self.zcen);
+                }
+            }
+            GainStage::LeftLineIn => codec.set_left_invol(InVoldB::from_raw(raw)),
+            GainStage::RightLineIn => codec.set_right_invol(InVoldB::from_raw(raw)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interface::mock::FrameLog;
+    use crate::VolumeLimit;
+
+    #[test]
+    fn fade_test() {
+        let mut codec = Wm8731::new(FrameLog::new());
+        codec.set_both_hpvol(HpVoldB::N20DB, false);
+        let mut engine = FadeEngine::new();
+        engine.start(&codec, FadeTarget::LeftHeadphone(HpVoldB::Z0DB), 200, 1000);
+        engine.start(&codec, FadeTarget::RightHeadphone(HpVoldB::Z0DB), 200, 1000);
+        codec.interface.clear();
+        assert!(!engine.tick(&mut codec, 1000));
+        assert!(engine.tick(&mut codec, 1100));
+        // both channels changed with one frame, by at most MAX_STEP
+        assert_eq!(codec.interface.frames().len(), 1);
+        assert_eq!(codec.both_hpvol(), (HpVoldB::N17DB, HpVoldB::N17DB));
+        // late tick caught up
+        while engine.tick(&mut codec, 1100) {}
+        assert_eq!(codec.interface.frames().len(), 4);
+        assert_eq!(codec.both_hpvol(), (HpVoldB::N10DB, HpVoldB::N10DB));
+        assert_eq!(engine.status(GainStage::LeftHeadphone), FadeStatus::Running);
+        // retarget from where the fade is
+        engine.start(&codec, FadeTarget::LeftHeadphone(HpVoldB::N20DB), 100, 1100);
+        while engine.tick(&mut codec, 1150) {}
+        assert_eq!(codec.left_hpvol(), HpVoldB::N15DB);
+        while engine.tick(&mut codec, 1300) {}
+        assert_eq!(codec.both_hpvol(), (HpVoldB::N20DB, HpVoldB::Z0DB));
+        assert_eq!(
+            engine.status(GainStage::LeftHeadphone),
+            FadeStatus::Completed
+        );
+        assert!(engine.is_idle());
+    }
+
+    #[test]
+    fn long_fade_test() {
+        let mut codec = Wm8731::new(FrameLog::new());
+        codec.set_both_hpvol(HpVoldB::MUTE, false);
+        let mut engine = FadeEngine::new();
+        // 6 hours
+        let duration = 6 * 3600 * 1000;
+        engine.start(&codec, FadeTarget::LeftHeadphone(HpVoldB::MAX), duration, 0);
+        while engine.tick(&mut codec, duration / 2) {}
+        let middle = (HpVoldB::MUTE.into_raw() + HpVoldB::MAX.into_raw()) / 2;
+        assert_eq!(codec.left_hpvol(), HpVoldB::from_raw(middle));
+        while engine.tick(&mut codec, duration) {}
+        assert_eq!(codec.left_hpvol(), HpVoldB::MAX);
+    }
+
+    #[test]
+    fn cancel_test() {
+        let mut codec = Wm8731::new(FrameLog::new());
+        let mut engine = FadeEngine::new();
+        engine.start(&codec, FadeTarget::LeftLineIn(InVoldB::P12DB), 100, 0);
+        engine.tick(&mut codec, 50);
+        engine.cancel(GainStage::LeftLineIn);
+        let vol = codec.left_invol();
+        assert!(!engine.tick(&mut codec, 100));
+        assert_eq!(codec.left_invol(), vol);
+        assert_eq!(engine.status(GainStage::LeftLineIn), FadeStatus::Idle);
+    }
+
+    #[test]
+    fn limit_test() {
+        let mut codec = Wm8731::new(FrameLog::new());
+        codec.set_bypass(false);
+        codec.set_both_hpvol(HpVoldB::N40DB, false);
+        codec.set_volume_limit(Some(VolumeLimit::new(HpVoldB::N20DB)));
+        let mut engine = FadeEngine::new();
+        engine.start(&codec, FadeTarget::LeftHeadphone(HpVoldB::Z0DB), 100, 0);
+        codec.interface.clear();
+        let mut now = 0;
+        while !engine.is_idle() && now < 1000 {
+            now += 1;
+            engine.tick(&mut codec, now);
+        }
+        assert!(now <= 100);
+        assert_eq!(codec.left_hpvol(), HpVoldB::N20DB);
+        // every frame changed the volume
+        let frames = codec.interface.frames();
+        assert!(frames.windows(2).all(|pair| pair[0] != pair[1]));
+        assert!(frames
+            .iter()
+            .all(|frame| frame & 0x7f <= HpVoldB::N20DB.into_raw() as u16));
+
+        // limit lowered during a fade up
+        codec.set_volume_limit(None);
+        engine.start(&codec, FadeTarget::LeftHeadphone(HpVoldB::Z0DB), 100, 0);
+        while engine.tick(&mut codec, 50) {}
+        codec.set_volume_limit(Some(VolumeLimit::new(HpVoldB::N15DB)));
+        codec.interface.clear();
+        while engine.tick(&mut codec, 200) {}
+        assert!(engine.is_idle());
+        assert!(codec.interface.frames().is_empty());
+        assert_eq!(codec.left_hpvol(), HpVoldB::N15DB);
+    }
+}
@@ -0,0 +1,186 @@
+//! Common interface of the gain types.
+//!
+//! [`InVoldB`](crate::InVoldB), [`HpVoldB`](crate::HpVoldB) and
+//! [`SideAttdB`](crate::SideAttdB) all store register bits on a regular dB scale. The [`Gain`]
+//! trait expose what they have in common, so generic code like UI sliders, MIDI mapping or fades
+//! can drive any gain stage of the codec.
+use crate::db::{Centibels, Rounding};
+use core::fmt;
+use core::iter::FusedIterator;
+use core::marker::PhantomData;
+
+///Error returned when trying to scale a value into a gain type.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum ScaleError {
+    ///The given range is null.
+    NullRange,
+    ///The given value is outside the given range.
+    OutOfRange,
+}
+
+impl fmt::Display for ScaleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScaleError::NullRange => f.write_str("null input range"),
+            ScaleError::OutOfRange => f.write_str("input outside of range"),
+        }
+    }
+}
+
+/// A gain value stored as raw register bits.
+///
+/// `MIN` and `MAX` follow the raw ordering, like the inherent constants of each type. For
+/// [`SideAttdB`](crate::SideAttdB) a greater raw value means a lower gain, so `MIN` is the
+/// highest gain and `STEP` is negative.
+pub trait Gain: Copy + Eq {
+    /// Lowest raw value.
+    const MIN: Self;
+    /// Highest raw value.
+    const MAX: Self;
+    /// Gain change when the raw value increase by one.
+    const STEP: Centibels;
+
+    ///Instanciate from the underlaying representation.
+    ///
+    ///# Safety
+    ///The raw value must be in between `MIN` and `MAX` raw values.
+    unsafe fn from_raw_unchecked(raw: u8) -> Self;
+
+    ///Return the raw underlaying representation
+    fn into_raw(self) -> u8;
+
+    /// Return the gain in centibels, or `None` for mute.
+    fn to_centibels(self) -> Option<Centibels>;
+
+    /// Return the value matching a gain. Saturate to the range.
+    fn from_centibels(cb: Centibels, rounding: Rounding) -> Self;
+
+    ///Instanciate from the underlaying representation.
+    ///
+    ///The raw value is clamped to fit in the range.
+    fn from_raw(raw: u8) -> Self {
+        let raw = raw.clamp(Self::MIN.into_raw(), Self::MAX.into_raw());
+        unsafe { Self::from_raw_unchecked(raw) }
+    }
+
+    /// Scale a value into the range. This function output an error when the input range is null
+    /// or when the input is outside the range
+    fn from_scaled(low_limit: i16, high_limit: i16, input: i16) -> Result<Self, ScaleError> {
+        if low_limit == high_limit {
+            return Err(ScaleError::NullRange);
+        } else if (low_limit < high_limit && (input < low_limit || input > high_limit))
+            || (low_limit > high_limit && (input > low_limit || input < high_limit))
+        {
+            return Err(ScaleError::OutOfRange);
+        }
+        let min = Self::MIN.into_raw() as i32;
+        let r1 = high_limit as i32 - low_limit as i32;
+        let r2 = Self::MAX.into_raw() as i32 - min;
+        let res = ((input as i32 - low_limit as i32) * r2 + r1 / 2) / r1 + min;
+        Ok(unsafe { Self::from_raw_unchecked(res as u8) })
+    }
+
+    ///Increase the raw value by one step. Saturated to `MAX`.
+    fn increase(&mut self) {
+        let raw = self.into_raw();
+        if raw < Self::MAX.into_raw() {
+            *self = unsafe { Self::from_raw_unchecked(raw + 1) };
+        }
+    }
+
+    ///Decrease the raw value by one step. Saturated to `MIN`.
+    fn decrease(&mut self) {
+        let raw = self.into_raw();
+        if raw > Self::MIN.into_raw() {
+            *self = unsafe { Self::from_raw_unchecked(raw - 1) };
+        }
+    }
+
+    /// Number of different values.
+    fn step_count() -> usize {
+        (Self::MAX.into_raw() - Self::MIN.into_raw()) as usize + 1
+    }
+
+    /// Iterate over every value, from `MIN` to `MAX`.
+    fn steps() -> Steps<Self> {
+        Steps {
+            front: Self::MIN.into_raw(),
+            back: Self::MAX.into_raw() + 1,
+            gain: PhantomData,
+        }
+    }
+}
+
+/// Iterator over every value of a gain type, see [`Gain::steps`].
+#[derive(Debug, Clone)]
+pub struct Steps<G> {
+    front: u8,
+    /// Exclusive.
+    back: u8,
+    gain: PhantomData<G>,
+}
+
+impl<G: Gain> Iterator for Steps<G> {
+    type Item = G;
+    fn next(&mut self) -> Option<G> {
+        if self.front < self.back {
+            self.front += 1;
+            Some(unsafe { G::from_raw_unchecked(self.front - 1) })
+        } else {
+            None
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = (self.back - self.front) as usize;
+        (len, Some(len))
+    }
+}
+
+impl<G: Gain> DoubleEndedIterator for Steps<G> {
+    fn next_back(&mut self) -> Option<G> {
+        if self.front < self.back {
+            self.back -= 1;
+            Some(unsafe { G::from_raw_unchecked(self.back) })
+        } else {
+            None
+        }
+    }
+}
+
+impl<G: Gain> ExactSizeIterator for Steps<G> {}
+
+impl<G: Gain> FusedIterator for Steps<G> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HpVoldB, InVoldB, SideAttdB};
+
+    /// Map a 7 bits MIDI controller to any gain.
+    fn midi<G: Gain>(cc: u8) -> G {
+        G::from_scaled(0, 127, cc as i16).unwrap()
+    }
+
+    #[test]
+    fn generic_test() {
+        assert_eq!(midi::<HpVoldB>(0), HpVoldB::MUTE);
+        assert_eq!(midi::<HpVoldB>(127), HpVoldB::P6DB);
+        assert_eq!(midi::<InVoldB>(127), InVoldB::P12DB);
+        assert_eq!(midi::<SideAttdB>(127), SideAttdB::N15DB);
+        assert_eq!(HpVoldB::steps().len(), HpVoldB::step_count());
+        assert_eq!(HpVoldB::steps().nth(1), Some(HpVoldB::N73DB));
+        assert_eq!(InVoldB::steps().next_back(), Some(InVoldB::P12DB));
+        let mut gains = SideAttdB::steps().map(Gain::to_centibels);
+        assert_eq!(gains.next(), Some(Some(Centibels(-60))));
+        assert_eq!(gains.next_back(), Some(Some(Centibels(-150))));
+        assert_eq!(<SideAttdB as Gain>::STEP, Centibels(-30));
+        let mut vol = <InVoldB as Gain>::MAX;
+        Gain::increase(&mut vol);
+        assert_eq!(vol, InVoldB::P12DB);
+        assert_eq!(
+            <HpVoldB as Gain>::from_centibels(Centibels(-5), Rounding::Down),
+            HpVoldB::N1DB
+        );
+    }
+}
@@ -0,0 +1,367 @@
+//! Test signal generator.
+//!
+//! [`Generator`] produces known stimuli for bring-up and production tests: sine, square,
+//! logarithmic sweep, white and pink noise, and sums of sines. Frequencies are exact for the DAC
+//! sampling frequency given, typically derived from [`SamplingRates`] and the core clock, and
+//! samples are produced at the interface word length, ready for
+//! [`SampleLayout`](crate::samples::SampleLayout).
+use crate::db::Centibels;
+use crate::interface::WriteFrame;
+use crate::math;
+use crate::samples::SampleLayout;
+use crate::{IwlV, SamplingRates, Wm8731};
+
+/// Maximum number of tones of a [`Waveform::Multitone`].
+pub const MAX_TONES: usize = 8;
+
+/// Rows of the pink noise generator, each one an octave lower.
+const PINK_ROWS: usize = 16;
+
+/// Shape of the generated signal.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Waveform {
+    Sine {
+        hz: u32,
+    },
+    Square {
+        hz: u32,
+    },
+    /// Logarithmic sweep, restarting once finished.
+    Sweep {
+        start_hz: u32,
+        end_hz: u32,
+        duration_ms: u32,
+    },
+    WhiteNoise,
+    /// Noise with -3dB per octave spectrum.
+    PinkNoise,
+    /// Sum of sines of equal amplitude. Only the first [`MAX_TONES`] non null frequencies are
+    /// used.
+    Multitone {
+        hz: [u32; MAX_TONES],
+    },
+}
+
+/// Fixed point signal generator.
+#[derive(Debug, Clone)]
+pub struct Generator {
+    waveform: Waveform,
+    sample_rate_hz: u32,
+    full_scale: u32,
+    amplitude: u32,
+    phases: [u32; MAX_TONES],
+    increments: [u32; MAX_TONES],
+    tones: usize,
+    /// Sample index inside the sweep.
+    position: u32,
+    /// Seed of the noise generator.
+    seed: u32,
+    pink_rows: [i32; PINK_ROWS],
+    pink_counter: u32,
+}
+
+impl Generator {
+    /// Generator at `sample_rate_hz`, with samples at `iwl` word length and a peak `level` in
+    /// dBFS.
+    pub fn new(waveform: Waveform, level: Centibels, sample_rate_hz: u32, iwl: IwlV) -> Self {
+        let mut generator = Self {
+            waveform,
+            sample_rate_hz: sample_rate_hz.max(1),
+            full_scale: iwl.full_scale(),
+            amplitude: 0,
+            phases: [0; MAX_TONES],
+            increments: [0; MAX_TONES],
+            tones: 0,
+            position: 0,
+            seed: 0x1234_5678,
+            pink_rows: [0; PINK_ROWS],
+            pink_counter: 0,
+        };
+        generator.set_level(level);
+        generator.set_waveform(waveform);
+        generator
+    }
+
+    /// Generator for a sampling configuration and a core clock frequency.
+    pub fn with_sampling(
+        waveform: Waveform,
+        level: Centibels,
+        rates: SamplingRates,
+        core_clock_hz: u32,
+        iwl: IwlV,
+    ) -> Self {
+        Self::new(waveform, level, rates.dac_rate(core_clock_hz), iwl)
+    }
+
+    /// Generator matching the codec DAC sampling frequency and word length. `None` if the clock
+    /// configuration is unknown.
+    pub fn for_codec<I: WriteFrame>(
+        waveform: Waveform,
+        level: Centibels,
+        codec: &Wm8731<I>,
+    ) -> Option<Self> {
+        let rate = codec.dac_rate_hz()?;
+        Some(Self::new(waveform, level, rate, codec.iwl()))
+    }
+
+    pub fn waveform(&self) -> Waveform {
+        self.waveform
+    }
+
+    /// Change the waveform, restarting phases.
+    pub fn set_waveform(&mut self, waveform: Waveform) {
+        self.waveform = waveform;
+        self.phases = [0; MAX_TONES];
+        self.position = 0;
+        self.tones = 0;
+        let rate = self.sample_rate_hz;
+        match waveform {
+            Waveform::Sine { hz } | Waveform::Square { hz } => {
+                self.increments[0] = increment(hz, rate);
+                self.tones = 1;
+            }
+            Waveform::Multitone { hz } => {
+                for hz in hz.iter().filter(|&&hz| hz != 0) {
+                    self.increments[self.tones] = increment(*hz, rate);
+                    self.tones += 1;
+                }
+            }
+            Waveform::Sweep { start_hz, .. } => {
+                self.increments[0] = increment(start_hz, rate);
+                self.tones = 1;
+            }
+            Waveform::WhiteNoise | Waveform::PinkNoise => (),
+        }
+    }
+
+    /// Set the peak level in dBFS. Saturate at full scale.
+    pub fn set_level(&mut self, level: Centibels) {
+        let ratio = math::centibels_to_ratio_q16(level.0 as i32) as u64;
+        let amplitude = (self.full_scale as u64 * ratio) >> 16;
+        self.amplitude = amplitude.min(self.full_scale as u64 - 1) as u32;
+    }
+
+    /// Peak amplitude of the samples.
+    pub fn amplitude(&self) -> u32 {
+        self.amplitude
+    }
+
+    pub fn sample_rate_hz(&self) -> u32 {
+        self.sample_rate_hz
+    }
+
+    /// Next mono sample.
+    pub fn next_sample(&mut self) -> i32 {
+        let amplitude = self.amplitude as i64;
+        match self.waveform {
+            Waveform::Sine { .. } => {
+                let sample = scale(math::sin_q31(self.phases[0]), amplitude);
+                self.phases[0] = self.phases[0].wrapping_add(self.increments[0]);
+                sample
+            }
+            Waveform::Square { .. } => {
+                let sample = if self.phases[0] < 1 << 31 {
+                    amplitude as i32
+                } else {
+                    -amplitude as i32
+                };
+                self.phases[0] = self.phases[0].wrapping_add(self.increments[0]);
+                sample
+            }
+            Waveform::Sweep {
+                start_hz,
+                end_hz,
+                duration_ms,
+            } => {
+                let sample = scale(math::sin_q31(self.phases[0]), amplitude);
+                self.phases[0] = self.phases[0].wrapping_add(self.increments[0]);
+                let length = (self.sample_rate_hz as u64 * duration_ms as u64 / 1000).max(1);
+                self.position += 1;
+                if self.position as u64 >= length {
+                    self.position = 0;
+                }
+                self.increments[0] = sweep_increment(
+                    start_hz,
+                    end_hz,
+                    self.position as u64,
+                    length,
+                    self.sample_rate_hz,
+                );
+                sample
+            }
+            Waveform::WhiteNoise => scale(self.random() as i32, amplitude),
+            Waveform::PinkNoise => {
+                // Voss-McCartney: row n is updated every 2^n samples
+                self.pink_counter = self.pink_counter.wrapping_add(1);
+                let row = (self.pink_counter.trailing_zeros() as usize).min(PINK_ROWS - 1);
+                self.pink_rows[row] = (self.random() as i32) >> 4;
+                let sum: i64 = self.pink_rows.iter().map(|&r| r as i64).sum();
+                scale(
+                    sum.clamp(i32::MIN as i64, i32::MAX as i64) as i32,
+                    amplitude,
+                )
+            }
+            Waveform::Multitone { .. } => {
+                let tones = self.tones.max(1) as i64;
+                let mut sum = 0i64;
+                for (phase, increment) in self
+                    .phases
+                    .iter_mut()
+                    .zip(&self.increments)
+                    .take(self.tones)
+                {
+                    sum += scale(math::sin_q31(*phase), amplitude / tones) as i64;
+                    *phase = phase.wrapping_add(*increment);
+                }
+                sum as i32
+            }
+        }
+    }
+
+    /// Fill a mono buffer.
+    pub fn fill_mono(&mut self, samples: &mut [i32]) {
+        for sample in samples {
+            *sample = self.next_sample();
+        }
+    }
+
+    /// Fill an interleaved stereo buffer, with the same signal on both channels.
+    pub fn fill(&mut self, samples: &mut [i32]) {
+        for frame in samples.chunks_exact_mut(2) {
+            let sample = self.next_sample();
+            frame[0] = sample;
+            frame[1] = sample;
+        }
+    }
+
+    /// Fill peripheral words, with the same signal on both channels. Return the number of words
+    /// written.
+    pub fn fill_words(&mut self, layout: &SampleLayout, words: &mut [u32]) -> usize {
+        let mut len = 0;
+        for slots in words.chunks_exact_mut(2) {
+            let sample = self.next_sample();
+            len += layout.pack(&[sample, sample], slots);
+        }
+        len
+    }
+
+    /// xorshift32 pseudo random generator.
+    fn random(&mut self) -> u32 {
+        let mut x = self.seed;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.seed = x;
+        x
+    }
+}
+
+/// Phase increment of a frequency, a full turn being `2^32`.
+fn increment(hz: u32, rate: u32) -> u32 {
+    ((hz as u64) << 32)
+        .checked_div(rate as u64)
+        .unwrap_or(0)
+        .min(u32::MAX as u64) as u32
+}
+
+/// Phase increment at `position` of a logarithmic sweep of `length` samples.
+fn sweep_increment(start_hz: u32, end_hz: u32, position: u64, length: u64, rate: u32) -> u32 {
+    let start = math::log2_q16(start_hz.max(1)) as i64;
+    let end = math::log2_q16(end_hz.max(1)) as i64;
+    let log2_hz = start + (end - start) * position as i64 / length as i64;
+    // increment = 2^(log2(hz) - log2(rate) + 32), exp2_q16 return 2^(x - 16) in Q16
+    let x = log2_hz - math::log2_q16(rate) as i64 + (16 << 16);
+    math::exp2_q16(x.clamp(i32::MIN as i64, i32::MAX as i64) as i32)
+}
+
+/// Multiply a Q31 value by an amplitude.
+fn scale(q31: i32, amplitude: i64) -> i32 {
+    ((q31 as i64 * amplitude) >> 31) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sine_test() {
+        let mut generator = Generator::new(
+            Waveform::Sine { hz: 1000 },
+            Centibels(-60),
+            48_000,
+            IwlV::Iwl16Bits,
+        );
+        assert_eq!(generator.amplitude(), 16_422);
+        let mut samples = [0; 48];
+        generator.fill_mono(&mut samples);
+        assert_eq!(samples[0], 0);
+        assert_eq!(samples[12], 16_421);
+        assert_eq!(samples[36], -16_422);
+        // one period exactly
+        assert!(generator.next_sample().abs() <= 1);
+    }
+
+    #[test]
+    fn sampling_test() {
+        let rates = SamplingRates::ADC256_DAC256_A;
+        let generator = Generator::with_sampling(
+            Waveform::Square { hz: 1000 },
+            Centibels::ZERO,
+            rates,
+            12_288_000,
+            IwlV::Iwl24Bits,
+        );
+        assert_eq!(generator.sample_rate_hz(), 48_000);
+        assert_eq!(generator.amplitude(), (1 << 23) - 1);
+    }
+
+    #[test]
+    fn sweep_test() {
+        let sweep = Waveform::Sweep {
+            start_hz: 100,
+            end_hz: 10_000,
+            duration_ms: 100,
+        };
+        let mut generator = Generator::new(sweep, Centibels::ZERO, 48_000, IwlV::Iwl16Bits);
+        let start = generator.increments[0];
+        for _ in 0..2400 {
+            generator.next_sample();
+        }
+        // half way of a log sweep is the geometric mean, 1kHz
+        let middle = generator.increments[0] as u64;
+        let expected = increment(1000, 48_000) as u64;
+        assert!(middle.abs_diff(expected) < expected / 100);
+        assert!((start as u64).abs_diff(increment(100, 48_000) as u64) < 1000);
+    }
+
+    #[test]
+    fn noise_test() {
+        for waveform in [Waveform::WhiteNoise, Waveform::PinkNoise] {
+            let mut generator = Generator::new(waveform, Centibels(-60), 48_000, IwlV::Iwl16Bits);
+            let mut samples = [0; 4800];
+            generator.fill_mono(&mut samples);
+            let peak = math::peak(&samples);
+            assert!(peak <= generator.amplitude());
+            assert!(peak > generator.amplitude() / 4);
+            let mean: i64 = samples.iter().map(|&s| s as i64).sum::<i64>() / 4800;
+            assert!(mean.abs() < 2000);
+        }
+    }
+
+    #[test]
+    fn multitone_test() {
+        let mut hz = [0; MAX_TONES];
+        hz[0] = 1000;
+        hz[1] = 3000;
+        let mut generator = Generator::new(
+            Waveform::Multitone { hz },
+            Centibels::ZERO,
+            48_000,
+            IwlV::Iwl16Bits,
+        );
+        let mut samples = [0; 96];
+        generator.fill(&mut samples);
+        assert!(math::peak(&samples) < 1 << 15);
+        assert_eq!(samples[0], samples[1]);
+    }
+}
@@ -0,0 +1,222 @@
+//! Pop free ADC input switching.
+//!
+//! Changing `INSEL` or `MICBOOST` steps the DC level at ADC input, which is recorded as a loud
+//! transient. Inputs are muted around the change and unmuted once it settled.
+//! [`Wm8731::switch_input`] block during the sequence, [`InputSwitch`] does the same from a tick
+//! and fades the line input volume when it only feeds the ADC.
+//!
+//! Each source has a stored gain, [`InputGains`], applied when it is selected.
+//!
+//! [`Wm8731::calibrate_dc_offset`] measure the ADC offset with inputs muted and keep subtracting
+//! it, for DC accurate recording.
+//!
+//! Times are in milliseconds, from any free running wrapping counter.
+use crate::fade::{FadeEngine, FadeTarget};
+use crate::interface::WriteFrame;
+use crate::{InVoldB, InselV, Wm8731};
+
+/// Time between muting inputs and changing `INSEL` or `MICBOOST`.
+pub const INPUT_MUTE_MS: u16 = 10;
+/// Time between changing `INSEL` or `MICBOOST` and unmuting the new input, letting input coupling
+/// settle.
+pub const INPUT_SETTLE_MS: u16 = 50;
+/// Time for the ADC high pass filter to settle at 48kHz. Its cutoff frequency is proportional to
+/// the sampling frequency.
+pub const HPF_SETTLE_MS: u16 = 500;
+
+/// Gains applied when an input is selected.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct InputGains {
+    /// Volume of both line inputs.
+    pub line: InVoldB,
+    pub micboost: bool,
+}
+
+impl InputGains {
+    /// Line at 0dB, without `MICBOOST`.
+    pub const DEFAULT: InputGains = InputGains {
+        line: InVoldB::Z0DB,
+        micboost: false,
+    };
+}
+
+impl Default for InputGains {
+    fn default() -> Self {
+        InputGains::DEFAULT
+    }
+}
+
+/// Step of an [`InputSwitch`].
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum SwitchPhase {
+    /// Line input volume fading down.
+    FadeOut,
+    /// Inputs muted, waiting before changing `INSEL` or `MICBOOST`.
+    Muted,
+    /// New input selected, waiting before unmuting it.
+    Settling,
+    /// Line input volume fading up to its stored gain.
+    FadeIn,
+    Done,
+}
+
+/// Non-blocking input switch, see [`Wm8731::switch_input`].
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct InputSwitch {
+    source: InselV,
+    phase: SwitchPhase,
+    fades: FadeEngine,
+    fade_ms: u32,
+    phase_ms: u32,
+    line_muted: (bool, bool),
+    line_fade: bool,
+}
+
+impl InputSwitch {
+    /// Start switching the ADC input to `source`.
+    ///
+    /// If the line input doesn't feed outputs through `BYPASS`, its volume is faded during
+    /// `fade_ms` before muting and after unmuting. Otherwise it is muted and unmuted at once.
+    pub fn start<I>(codec: &Wm8731<I>, source: InselV, fade_ms: u32, now: u32) -> Self
+    where
+        I: WriteFrame,
+    {
+        let mut switch = Self {
+            source,
+            phase: SwitchPhase::FadeOut,
+            fades: FadeEngine::new(),
+            fade_ms,
+            phase_ms: now,
+            line_muted: codec.both_inmute(),
+            line_fade: fade_ms > 0 && !codec.bypass(),
+        };
+        if switch.line_fade && codec.insel() == InselV::Line {
+            switch.fade_line(codec, InVoldB::MIN, now);
+        }
+        switch
+    }
+
+    pub fn source(&self) -> InselV {
+        self.source
+    }
+
+    pub fn phase(&self) -> SwitchPhase {
+        self.phase
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.phase == SwitchPhase::Done
+    }
+
+    /// Advance the switch. Return the current phase.
+    pub fn tick<I>(&mut self, codec: &mut Wm8731<I>, now: u32) -> SwitchPhase
+    where
+        I: WriteFrame,
+    {
+        let elapsed = now.wrapping_sub(self.phase_ms);
+        match self.phase {
+            SwitchPhase::FadeOut => {
+                self.fades.tick(codec, now);
+                if self.fades.is_idle() {
+                    codec.mute_adc_inputs(self.source);
+                    self.enter(SwitchPhase::Muted, now);
+                }
+            }
+            SwitchPhase::Muted if elapsed >= INPUT_MUTE_MS as u32 => {
+                let gains = codec.input_gains();
+                let line = match self.source {
+                    InselV::Line if self.line_fade => InVoldB::MIN,
+                    _ => gains.line,
+                };
+                let faded_out = self.line_fade && codec.insel() == InselV::Line;
+                codec.select_input(self.source, line);
+                // line input left at its faded out volume
+                if faded_out && self.source == InselV::Mic {
+                    codec.set_both_invol(gains.line);
+                }
+                self.enter(SwitchPhase::Settling, now);
+            }
+            SwitchPhase::Settling if elapsed >= INPUT_SETTLE_MS as u32 => {
+                codec.unmute_adc_input(self.line_muted);
+                if self.line_fade && self.source == InselV::Line {
+                    self.fade_line(codec, codec.input_gains().line, now);
+                    self.enter(SwitchPhase::FadeIn, now);
+                } else {
+                    self.enter(SwitchPhase::Done, now);
+                }
+            }
+            SwitchPhase::FadeIn => {
+                self.fades.tick(codec, now);
+                if self.fades.is_idle() {
+                    self.enter(SwitchPhase::Done, now);
+                }
+            }
+            _ => (),
+        }
+        self.phase
+    }
+
+    fn enter(&mut self, phase: SwitchPhase, now: u32) {
+        self.phase = phase;
+        self.phase_ms = now;
+    }
+
+    fn fade_line<I: WriteFrame>(&mut self, codec: &Wm8731<I>, volume: InVoldB, now: u32) {
+        self.fades
+            .start(codec, FadeTarget::LeftLineIn(volume), self.fade_ms, now);
+        self.fades
+            .start(codec, FadeTarget::RightLineIn(volume), self.fade_ms, now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interface::mock::{FrameLog, NoDelay};
+    use crate::UseCase;
+
+    #[test]
+    fn tick_test() {
+        let mut codec = Wm8731::new(FrameLog::new());
+        codec.power_up_use_case(UseCase::RecordLine, &mut NoDelay::default());
+        codec.set_both_inmute(false);
+        codec.set_input_gains(InputGains {
+            line: InVoldB::P6DB,
+            micboost: true,
+        });
+        let mut switch = InputSwitch::start(&codec, InselV::Mic, 30, 0);
+        let mut now = 0;
+        while !switch.is_done() && now < 1000 {
+            now += 1;
+            let previous = (codec.insel(), codec.micboost());
+            let phase = switch.tick(&mut codec, now);
+            if (codec.insel(), codec.micboost()) != previous {
+                assert_eq!(phase, SwitchPhase::Settling);
+            }
+            if phase == SwitchPhase::Settling {
+                assert!(codec.mutemic());
+                assert_eq!(codec.both_inmute(), (true, true));
+            }
+        }
+        assert!(now >= 30 + (INPUT_MUTE_MS + INPUT_SETTLE_MS) as u32);
+        assert_eq!(codec.insel(), InselV::Mic);
+        assert!(codec.micboost() && !codec.mutemic() && !codec.micpd());
+        assert_eq!(codec.both_invol(), (InVoldB::P6DB, InVoldB::P6DB));
+        assert_eq!(codec.both_inmute(), (true, true));
+
+        let mut switch = InputSwitch::start(&codec, InselV::Line, 30, 0);
+        assert_eq!(switch.phase(), SwitchPhase::FadeOut);
+        let mut now = 0;
+        while switch.tick(&mut codec, now) != SwitchPhase::FadeIn {
+            now += 1;
+        }
+        assert_eq!(codec.both_invol(), (InVoldB::MIN, InVoldB::MIN));
+        assert_eq!(codec.both_inmute(), (false, false));
+        while !switch.is_done() {
+            now += 1;
+            switch.tick(&mut codec, now);
+        }
+        assert_eq!(codec.both_invol(), (InVoldB::P6DB, InVoldB::P6DB));
+        assert!(codec.mutemic());
+    }
+}
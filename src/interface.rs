@@ -128,3 +128,51 @@ where
         let _ = self.cs.set_high();
     }
 }
+
+/// Test helpers.
+#[cfg(test)]
+pub(crate) mod mock {
+    use super::{Frame, WriteFrame};
+    use embedded_hal::blocking::delay::DelayMs;
+
+    /// Record sent frames.
+    #[derive(Debug)]
+    pub struct FrameLog {
+        pub frames: [u16; 512],
+        pub len: usize,
+    }
+
+    impl FrameLog {
+        pub fn new() -> Self {
+            Self {
+                frames: [0; 512],
+                len: 0,
+            }
+        }
+        pub fn frames(&self) -> &[u16] {
+            &self.frames[..self.len]
+        }
+        pub fn clear(&mut self) {
+            self.len = 0;
+        }
+    }
+
+    impl WriteFrame for FrameLog {
+        fn write(&mut self, frame: Frame) {
+            self.frames[self.len] = frame.data;
+            self.len += 1;
+        }
+    }
+
+    /// Delay doing nothing, recording the total requested time.
+    #[derive(Debug, Default)]
+    pub struct NoDelay {
+        pub total_ms: u32,
+    }
+
+    impl DelayMs<u16> for NoDelay {
+        fn delay_ms(&mut self, ms: u16) {
+            self.total_ms += ms as u32;
+        }
+    }
+}
@@ -15,18 +15,54 @@
 #![no_std]
 #![doc(html_root_url = "https://docs.rs/embedded-hal/0.2.0/")]
 
+pub mod agc;
+pub mod clip;
+pub mod clock;
+pub mod current;
+pub mod db;
+pub mod diagnostics;
+pub mod fade;
+pub mod gain;
+pub mod generator;
+pub mod input;
 pub mod interface;
+pub mod limit;
+pub mod link;
+pub mod math;
+pub mod meter;
+pub mod power;
 pub mod prelude;
 pub mod registers;
+pub mod routing;
+pub mod samples;
+pub mod scene;
+pub mod self_test;
+pub mod sim;
+pub mod stereo;
+pub mod taper;
 
+use clock::{ClkoutDiv, ClockConfig, ClockSource};
+use current::{CurrentTable, OperatingPoint, SupplyCurrent, SupplyVoltage};
+use db::Centibels;
+use diagnostics::{Diagnostics, Warning};
+use embedded_hal::blocking::delay::DelayMs;
+use input::InputGains;
 use interface::WriteFrame;
+use limit::VolumeLimit;
+use link::LinkDescriptor;
+use power::{PathRouting, PowerProfile, UseCase};
+use routing::{Route, RoutingError, Sink, Source};
+
+pub use gain::Gain;
 
 #[doc(inline)]
 pub use registers::analogue_audio_path::{InselV, SideAttdB};
 #[doc(inline)]
+pub use registers::audio_format::{AudioFormat, AudioFormatError, AudioStandard, DspMode};
+#[doc(inline)]
 pub use registers::digital_audio_interface::{FormatV, IwlV, MsV};
 #[doc(inline)]
-pub use registers::digital_audio_path::DeempV;
+pub use registers::digital_audio_path::{AdcHighPass, DeempV};
 #[doc(inline)]
 pub use registers::headphone_out::HpVoldB;
 #[doc(inline)]
@@ -46,6 +82,21 @@ use registers::power_down::PowerDown;
 use registers::reset::Reset;
 use registers::sampling::Sampling;
 
+/// Copy of every register content stored by a [`Wm8731`] driver.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct Snapshot {
+    left_line_in: LeftLineIn,
+    right_line_in: RightLineIn,
+    left_hpvol: HpVoldB,
+    right_hpvol: HpVoldB,
+    analogue_audio_path: AnalogueAudioPath,
+    digital_audio_path: DigitalAudioPath,
+    power_down: PowerDown,
+    digital_audio_interface: DigitalAudioInterface,
+    sampling: Sampling,
+    active: Active,
+}
+
 /// Wm8731 Control Interface Abstraction.
 ///
 /// This type provide abstraction of Wm8731 Control Interface. The control is mainly done through
@@ -64,6 +115,12 @@ use registers::sampling::Sampling;
 ///
 /// In the following example, `i2c1` implements [`embedded_hal::blocking::i2c::Write`]
 /// ```
+/// # struct I2c;
+/// # impl embedded_hal::blocking::i2c::Write for I2c {
+/// #     type Error = ();
+/// #     fn write(&mut self, _: u8, _: &[u8]) -> Result<(), ()> { Ok(()) }
+/// # }
+/// # let i2c1 = I2c;
 /// use wm8731_another_hal::prelude::*;
 ///
 /// let address = 0b001_1010; //Wm8731 address when CSB is low
@@ -75,6 +132,18 @@ use registers::sampling::Sampling;
 /// In the following example, `spi1` implements [`embedded_hal::blocking::spi::Write<u8>`] and `cs_pin`
 /// implements [`embedded_hal::digital::v2::OutputPin`]
 /// ```
+/// # struct Spi;
+/// # impl embedded_hal::blocking::spi::Write<u8> for Spi {
+/// #     type Error = ();
+/// #     fn write(&mut self, _: &[u8]) -> Result<(), ()> { Ok(()) }
+/// # }
+/// # struct Pin;
+/// # impl embedded_hal::digital::v2::OutputPin for Pin {
+/// #     type Error = ();
+/// #     fn set_low(&mut self) -> Result<(), ()> { Ok(()) }
+/// #     fn set_high(&mut self) -> Result<(), ()> { Ok(()) }
+/// # }
+/// # let (spi1, cs_pin) = (Spi, Pin);
 /// use wm8731_another_hal::prelude::*;
 ///
 /// let wm8731 = Wm8731::new(SPIInterfaceU8::new(spi1, cs_pin));
@@ -112,6 +181,11 @@ where
     digital_audio_interface: DigitalAudioInterface,
     sampling: Sampling,
     active: Active,
+    clock_source: Option<ClockSource>,
+    suspended: Option<Snapshot>,
+    volume_limit: Option<VolumeLimit>,
+    limit_hit: bool,
+    input_gains: InputGains,
 }
 
 /// Constructor and Destructor.
@@ -133,6 +207,11 @@ where
             digital_audio_interface: Default::default(),
             sampling: Default::default(),
             active: Default::default(),
+            clock_source: None,
+            suspended: None,
+            volume_limit: None,
+            limit_hit: false,
+            input_gains: InputGains::DEFAULT,
         };
         codec.interface.write(Reset::new().to_frame());
         codec
@@ -168,7 +247,8 @@ where
         self.interface.write(self.active.to_frame());
     }
 
-    /// Reset the codec. All configuration is lost.
+    /// Reset the codec. All configuration is lost, except the volume limit, which is applied to
+    /// the default headphone volumes.
     pub fn reset(&mut self) {
         self.interface.write(Reset::new().to_frame());
         self.left_line_in = Default::default();
@@ -181,6 +261,10 @@ where
         self.digital_audio_interface = Default::default();
         self.sampling = Default::default();
         self.active = Default::default();
+        self.clock_source = None;
+        self.suspended = None;
+        self.input_gains = InputGains::DEFAULT;
+        self.enforce_volume_limit();
     }
 }
 
@@ -277,6 +361,7 @@ where
     /// noise. The volume may never change if signal at gain stage input get never close to +/-
     /// 20mv.
     pub fn set_left_hpvol(&mut self, volume: HpVoldB, zcen: bool) {
+        let volume = self.limit_hpvol(volume);
         self.left_hpvol = volume;
         self.interface.write(
             LeftHeadphoneOut::default()
@@ -295,6 +380,7 @@ where
     /// noise. The volume may never change if signal at gain stage input get never close to +/-
     /// 20mv.
     pub fn set_right_hpvol(&mut self, volume: HpVoldB, zcen: bool) {
+        let volume = self.limit_hpvol(volume);
         self.right_hpvol = volume;
         self.interface.write(
             RightHeadphoneOut::default()
@@ -313,6 +399,7 @@ where
     /// noise. The volume may never change if signal at gain stage input get never close to +/-
     /// 20mv.
     pub fn set_both_hpvol(&mut self, volume: HpVoldB, zcen: bool) {
+        let volume = self.limit_hpvol(volume);
         self.left_hpvol = volume;
         self.right_hpvol = volume;
         self.interface.write(
@@ -375,6 +462,7 @@ where
 
     pub fn set_bypass(&mut self, value: bool) {
         self.analogue_audio_path.set_bypass(value);
+        self.enforce_volume_limit();
         self.interface.write(self.analogue_audio_path.to_frame());
     }
 
@@ -385,6 +473,7 @@ where
 
     pub fn set_sidetone(&mut self, value: bool) {
         self.analogue_audio_path.set_sidetone(value);
+        self.enforce_volume_limit();
         self.interface.write(self.analogue_audio_path.to_frame());
     }
 
@@ -448,6 +537,17 @@ where
         self.digital_audio_path.set_hpor(value);
         self.interface.write(self.digital_audio_path.to_frame());
     }
+
+    /// ADC high pass filter mode, from `ADCHPD` and `HPOR`.
+    pub fn adc_high_pass(&self) -> AdcHighPass {
+        self.digital_audio_path.adc_high_pass()
+    }
+
+    /// Set `ADCHPD` and `HPOR` at once.
+    pub fn set_adc_high_pass(&mut self, value: AdcHighPass) {
+        self.digital_audio_path.set_adc_high_pass(value);
+        self.interface.write(self.digital_audio_path.to_frame());
+    }
 }
 
 /// Power Down Control.
@@ -497,6 +597,9 @@ where
         self.interface.write(self.power_down.to_frame());
     }
     pub fn set_outpd(&mut self, value: bool) {
+        if !value {
+            self.enforce_volume_limit();
+        }
         self.power_down.set_outpd(value);
         self.interface.write(self.power_down.to_frame());
     }
@@ -537,6 +640,11 @@ where
     pub fn bclkinv(&self) -> bool {
         self.digital_audio_interface.bclkinv()
     }
+    /// Get `FORMAT`, `IWL`, `LRP` and `BCLKINV` as a whole. Fail if they were set to a forbidden
+    /// combination through individual setters.
+    pub fn audio_format(&self) -> Result<AudioFormat, AudioFormatError> {
+        self.digital_audio_interface.audio_format()
+    }
 
     pub fn set_format(&mut self, value: FormatV) {
         if !self.active.get() {
@@ -568,6 +676,12 @@ where
             self.digital_audio_interface.set_bclkinv(value);
         }
     }
+    /// Set `FORMAT`, `IWL`, `LRP` and `BCLKINV` at once from a validated format.
+    pub fn set_audio_format(&mut self, value: AudioFormat) {
+        if !self.active.get() {
+            self.digital_audio_interface.set_audio_format(value);
+        }
+    }
 }
 
 /// Sampling Control. Value stored only if inactive, sent only during activation.
@@ -603,3 +717,1174 @@ where
         }
     }
 }
+
+/// Power sequencing.
+impl<I> Wm8731<I>
+where
+    I: WriteFrame,
+{
+    /// Power up the codec following the datasheet sequence minimising pops.
+    ///
+    /// The sequence is:
+    ///  1. power every block of the profile except outputs, and wait for VMID to settle.
+    ///  2. send every register content, so paths are configured as stored in this driver.
+    ///  3. activate the digital audio interface.
+    ///  4. power outputs if they are part of the profile.
+    ///
+    /// Timings are defined in the [`power`] module.
+    pub fn power_up<D>(&mut self, profile: PowerProfile, delay: &mut D)
+    where
+        D: DelayMs<u16>,
+    {
+        self.set_power_profile_shadow(profile);
+        self.power_down.set_poweroff(false);
+        self.power_up_sequence(true, delay);
+    }
+
+    /// Power up sequence using blocks of the stored `PowerDown` content.
+    fn power_up_sequence<D>(&mut self, activate: bool, delay: &mut D)
+    where
+        D: DelayMs<u16>,
+    {
+        let outputs = !self.power_down.outpd();
+        self.power_down.set_outpd(true);
+        self.interface.write(self.power_down.to_frame());
+        delay.delay_ms(power::VMID_SETTLE_MS);
+        self.clamp_hpvol_shadow();
+        self.write_paths();
+        if activate {
+            self.activate();
+        }
+        if outputs {
+            self.power_down.set_outpd(false);
+            self.interface.write(self.power_down.to_frame());
+        }
+    }
+
+    /// Power down the codec following the datasheet sequence minimising pops.
+    ///
+    /// The sequence is:
+    ///  1. mute the DAC, and wait for the soft mute to complete.
+    ///  2. power down outputs, and wait for them to discharge.
+    ///  3. deactivate the digital audio interface.
+    ///  4. power down every block and the whole device.
+    ///
+    /// The DAC is muted without modifying the `DACMU` value stored in this driver, so
+    /// [`Wm8731::power_up`] restore it. Timings are defined in the [`power`] module.
+    pub fn power_down<D>(&mut self, delay: &mut D)
+    where
+        D: DelayMs<u16>,
+    {
+        let mut muted = self.digital_audio_path;
+        muted.set_dacmu(true);
+        self.interface.write(muted.to_frame());
+        delay.delay_ms(power::SOFT_MUTE_MS);
+        self.power_down.set_outpd(true);
+        self.interface.write(self.power_down.to_frame());
+        delay.delay_ms(power::OUTPUT_DISCHARGE_MS);
+        self.deactivate();
+        self.power_down
+            .set_lineinpd(true)
+            .set_micpd(true)
+            .set_adcpd(true)
+            .set_dacpd(true)
+            .set_poweroff(true);
+        self.interface.write(self.power_down.to_frame());
+    }
+
+    /// Get blocks currently powered.
+    pub fn power_profile(&self) -> PowerProfile {
+        PowerProfile {
+            line_in: !self.power_down.lineinpd(),
+            mic: !self.power_down.micpd(),
+            adc: !self.power_down.adcpd(),
+            dac: !self.power_down.dacpd(),
+            outputs: !self.power_down.outpd(),
+        }
+    }
+
+    /// Get Analogue Audio Path routing fields.
+    pub fn routing(&self) -> PathRouting {
+        PathRouting {
+            dacsel: self.analogue_audio_path.dacsel(),
+            bypass: self.analogue_audio_path.bypass(),
+            insel: self.analogue_audio_path.insel(),
+            sidetone: self.analogue_audio_path.sidetone(),
+        }
+    }
+
+    /// Set Analogue Audio Path routing fields at once.
+    pub fn set_routing(&mut self, value: PathRouting) {
+        self.set_routing_shadow(value);
+        self.enforce_volume_limit();
+        self.interface.write(self.analogue_audio_path.to_frame());
+    }
+
+    /// Switch to a use case while the codec is running.
+    ///
+    /// Newly required blocks are powered before routing change, and blocks no longer required are
+    /// powered down after, so a path is never routed through an unpowered block. Use
+    /// [`Wm8731::power_up_use_case`] to start from a powered down codec.
+    ///
+    /// Mutes are not changed, see [`UseCase`].
+    pub fn set_use_case(&mut self, use_case: UseCase) {
+        let current = self.power_profile();
+        let target = use_case.power_profile();
+        let union = PowerProfile {
+            line_in: current.line_in || target.line_in,
+            mic: current.mic || target.mic,
+            adc: current.adc || target.adc,
+            dac: current.dac || target.dac,
+            outputs: current.outputs || target.outputs,
+        };
+        self.write_power_profile(union);
+        self.set_routing(use_case.routing());
+        self.write_power_profile(target);
+    }
+
+    /// Power up the codec for a use case, see [`Wm8731::power_up`]. Mutes are not changed, see
+    /// [`UseCase`].
+    pub fn power_up_use_case<D>(&mut self, use_case: UseCase, delay: &mut D)
+    where
+        D: DelayMs<u16>,
+    {
+        self.set_routing_shadow(use_case.routing());
+        self.power_up(use_case.power_profile(), delay);
+    }
+
+    fn set_routing_shadow(&mut self, value: PathRouting) {
+        self.analogue_audio_path
+            .set_dacsel(value.dacsel)
+            .set_bypass(value.bypass)
+            .set_insel(value.insel)
+            .set_sidetone(value.sidetone);
+    }
+
+    fn set_power_profile_shadow(&mut self, value: PowerProfile) {
+        self.power_down
+            .set_lineinpd(!value.line_in)
+            .set_micpd(!value.mic)
+            .set_adcpd(!value.adc)
+            .set_dacpd(!value.dac)
+            .set_outpd(!value.outputs);
+    }
+
+    /// Send content of every path and volume register. Digital audio interface and sampling are
+    /// sent during activation.
+    fn write_paths(&mut self) {
+        let mut left_line_in = self.left_line_in;
+        left_line_in.set_both(false);
+        self.interface.write(left_line_in.to_frame());
+        let mut right_line_in = self.right_line_in;
+        right_line_in.set_both(false);
+        self.interface.write(right_line_in.to_frame());
+        self.interface.write(
+            LeftHeadphoneOut::default()
+                .set_vol(self.left_hpvol)
+                .to_frame(),
+        );
+        self.interface.write(
+            RightHeadphoneOut::default()
+                .set_vol(self.right_hpvol)
+                .to_frame(),
+        );
+        self.interface.write(self.analogue_audio_path.to_frame());
+        self.interface.write(self.digital_audio_path.to_frame());
+    }
+}
+
+/// Signal routing graph.
+///
+/// Blocks needed by a path are powered before it is routed, and blocks no longer used by any path
+/// are powered down after it is removed.
+impl<I> Wm8731<I>
+where
+    I: WriteFrame,
+{
+    /// Route `source` to `sink`. The ADC has a single input, so connecting a source to it
+    /// replace the previous one.
+    pub fn connect(&mut self, source: Source, sink: Sink) -> Result<(), RoutingError> {
+        if !routing::is_routable(source, sink) {
+            return Err(RoutingError { source, sink });
+        }
+        let mut profile = self.power_profile();
+        match source {
+            Source::LineIn => profile.line_in = true,
+            Source::Mic => profile.mic = true,
+            Source::Dac => profile.dac = true,
+        }
+        match sink {
+            Sink::Adc => profile.adc = true,
+            Sink::Outputs => profile.outputs = true,
+        }
+        self.write_power_profile(profile);
+        let previous = self.insel();
+        let mut routing = self.routing();
+        match (source, sink) {
+            (Source::LineIn, Sink::Adc) => routing.insel = InselV::Line,
+            (Source::Mic, Sink::Adc) => routing.insel = InselV::Mic,
+            (Source::LineIn, Sink::Outputs) => routing.bypass = true,
+            (Source::Mic, Sink::Outputs) => routing.sidetone = true,
+            _ => routing.dacsel = true,
+        }
+        if routing != self.routing() {
+            self.set_routing(routing);
+        }
+        match source {
+            Source::LineIn if self.both_inmute() != (false, false) => self.set_both_inmute(false),
+            Source::Mic if sink == Sink::Adc && self.mutemic() => self.set_mutemic(false),
+            Source::Dac if self.dacmu() => self.set_dacmu(false),
+            _ => (),
+        }
+        if sink == Sink::Adc && previous != routing.insel {
+            if previous == InselV::Mic && !self.mutemic() {
+                self.set_mutemic(true);
+            }
+            self.power_down_unused();
+        }
+        Ok(())
+    }
+
+    /// Remove the path from `source` to `sink`.
+    pub fn disconnect(&mut self, source: Source, sink: Sink) -> Result<(), RoutingError> {
+        if !routing::is_routable(source, sink) {
+            return Err(RoutingError { source, sink });
+        }
+        let mut routing = self.routing();
+        match (source, sink) {
+            (_, Sink::Adc) => {
+                let insel = match source {
+                    Source::Mic => InselV::Mic,
+                    _ => InselV::Line,
+                };
+                if self.insel() != insel {
+                    return Ok(());
+                }
+                if source == Source::Mic && !self.mutemic() {
+                    self.set_mutemic(true);
+                }
+                let mut profile = self.power_profile();
+                profile.adc = false;
+                self.write_power_profile(profile);
+            }
+            (Source::LineIn, _) => routing.bypass = false,
+            (Source::Mic, _) => routing.sidetone = false,
+            (Source::Dac, _) => routing.dacsel = false,
+        }
+        if routing != self.routing() {
+            self.set_routing(routing);
+        }
+        self.power_down_unused();
+        Ok(())
+    }
+
+    /// Returns `true` if signal flows from `source` to `sink`.
+    pub fn is_connected(&self, source: Source, sink: Sink) -> bool {
+        let power = &self.power_down;
+        let path = &self.analogue_audio_path;
+        let line_in = !power.lineinpd() && self.both_inmute() != (true, true);
+        let mic = !power.micpd();
+        let adc = !power.adcpd();
+        let outputs = !power.outpd();
+        match (source, sink) {
+            (Source::LineIn, Sink::Adc) => adc && line_in && path.insel() == InselV::Line,
+            (Source::Mic, Sink::Adc) => {
+                adc && mic && !path.mutemic() && path.insel() == InselV::Mic
+            }
+            (Source::LineIn, Sink::Outputs) => outputs && line_in && path.bypass(),
+            (Source::Mic, Sink::Outputs) => outputs && mic && path.sidetone(),
+            (Source::Dac, Sink::Outputs) => {
+                outputs && !power.dacpd() && !self.dacmu() && path.dacsel()
+            }
+            (Source::Dac, Sink::Adc) => false,
+        }
+    }
+
+    /// Paths carrying signal, with their gain.
+    pub fn routes(&self) -> impl Iterator<Item = Route> + '_ {
+        routing::PATHS
+            .into_iter()
+            .filter(|&(source, sink)| self.is_connected(source, sink))
+            .map(|(source, sink)| {
+                let (left, right) = self.route_gains(source, sink);
+                Route {
+                    source,
+                    sink,
+                    left,
+                    right,
+                }
+            })
+    }
+
+    fn route_gains(&self, source: Source, sink: Sink) -> (Option<Centibels>, Option<Centibels>) {
+        let line_in = |vol: InVoldB, mute: bool| (!mute).then(|| vol.to_centibels());
+        let (left, right) = match source {
+            Source::LineIn => (
+                line_in(self.left_invol(), self.left_inmute()),
+                line_in(self.right_invol(), self.right_inmute()),
+            ),
+            Source::Mic => {
+                let boost = if self.micboost() {
+                    Centibels(200)
+                } else {
+                    Centibels::ZERO
+                };
+                let gain = match sink {
+                    Sink::Adc => boost,
+                    Sink::Outputs => boost + self.sideatt().to_centibels(),
+                };
+                (Some(gain), Some(gain))
+            }
+            Source::Dac => (Some(Centibels::ZERO), Some(Centibels::ZERO)),
+        };
+        match sink {
+            Sink::Adc => (left, right),
+            Sink::Outputs => {
+                let headphone =
+                    |gain: Option<Centibels>, vol: HpVoldB| Some(gain? + vol.to_centibels()?);
+                (
+                    headphone(left, self.left_hpvol),
+                    headphone(right, self.right_hpvol),
+                )
+            }
+        }
+    }
+
+    /// Power down blocks used by no path.
+    fn power_down_unused(&mut self) {
+        let path = self.analogue_audio_path;
+        let adc = !self.power_down.adcpd();
+        let mut profile = self.power_profile();
+        profile.line_in &= path.bypass() || (adc && path.insel() == InselV::Line);
+        profile.mic &= path.sidetone() || (adc && path.insel() == InselV::Mic);
+        profile.dac &= path.dacsel();
+        profile.outputs &= path.dacsel() || path.bypass() || path.sidetone();
+        self.write_power_profile(profile);
+    }
+
+    /// Write blocks to power. Headphone volumes are limited before outputs are powered.
+    fn write_power_profile(&mut self, profile: PowerProfile) {
+        if profile.outputs {
+            self.enforce_volume_limit();
+        }
+        if profile != self.power_profile() {
+            self.set_power_profile_shadow(profile);
+            self.interface.write(self.power_down.to_frame());
+        }
+    }
+}
+
+/// ADC input switching and calibration.
+///
+/// Inputs are muted while `INSEL` or `MICBOOST` change, see the [`input`] module.
+impl<I> Wm8731<I>
+where
+    I: WriteFrame,
+{
+    /// Get gains applied when an input is selected by [`Wm8731::switch_input`].
+    pub fn input_gains(&self) -> InputGains {
+        self.input_gains
+    }
+
+    /// Set gains applied when an input is selected by [`Wm8731::switch_input`]. Nothing is sent:
+    /// switch to the current input to apply them.
+    pub fn set_input_gains(&mut self, gains: InputGains) {
+        self.input_gains = gains;
+    }
+
+    /// Select the ADC input and apply its stored gain, blocking until done.
+    ///
+    /// The current and new inputs are muted, `INSEL`, `MICBOOST` and line input volume are
+    /// changed after [`input::INPUT_MUTE_MS`], and the new input is unmuted after
+    /// [`input::INPUT_SETTLE_MS`]. The new input is powered if needed. Line inputs feeding outputs
+    /// through `BYPASS` get back their previous mute when the microphone is selected.
+    ///
+    /// See [`InputSwitch`](input::InputSwitch) for a non-blocking switch.
+    pub fn switch_input<D>(&mut self, source: InselV, delay: &mut D)
+    where
+        D: DelayMs<u16>,
+    {
+        let line_muted = self.both_inmute();
+        self.mute_adc_inputs(source);
+        delay.delay_ms(input::INPUT_MUTE_MS);
+        self.select_input(source, self.input_gains.line);
+        delay.delay_ms(input::INPUT_SETTLE_MS);
+        self.unmute_adc_input(line_muted);
+    }
+
+    /// Measure the ADC DC offset and subtract it from now on.
+    ///
+    /// Line inputs and microphone are muted, the high pass filter runs during
+    /// [`input::HPF_SETTLE_MS`], scaled by the ADC sampling frequency when known, and is then
+    /// switched to [`AdcHighPass::FrozenOffset`]. Mutes are restored before returning.
+    ///
+    /// Return `false`, doing nothing, if the ADC is powered down or the digital audio interface is
+    /// inactive, since the filter doesn't run.
+    pub fn calibrate_dc_offset<D>(&mut self, delay: &mut D) -> bool
+    where
+        D: DelayMs<u16>,
+    {
+        if self.power_down.adcpd() || !self.is_active() {
+            return false;
+        }
+        let (line_muted, mutemic) = (self.both_inmute(), self.mutemic());
+        if line_muted != (true, true) {
+            self.set_both_inmute(true);
+        }
+        if !mutemic {
+            self.set_mutemic(true);
+        }
+        self.set_adc_high_pass(AdcHighPass::Enabled);
+        let rate = self.adc_rate_hz().unwrap_or(48_000);
+        let settle_ms = input::HPF_SETTLE_MS as u32 * 48_000 / rate;
+        delay.delay_ms(settle_ms.min(u16::MAX as u32) as u16);
+        self.set_adc_high_pass(AdcHighPass::FrozenOffset);
+        if line_muted != (true, true) {
+            self.set_left_inmute(line_muted.0);
+            self.set_right_inmute(line_muted.1);
+        }
+        if !mutemic {
+            self.set_mutemic(false);
+        }
+        true
+    }
+
+    /// Mute the current ADC input and `source`.
+    fn mute_adc_inputs(&mut self, source: InselV) {
+        let inputs = [self.insel(), source];
+        if inputs.contains(&InselV::Line) && self.both_inmute() != (true, true) {
+            self.set_both_inmute(true);
+        }
+        if inputs.contains(&InselV::Mic) && !self.mutemic() {
+            self.set_mutemic(true);
+        }
+    }
+
+    /// Power and select `source`. Line input volume is set to `line` when selecting line inputs,
+    /// `MICBOOST` to its stored value when selecting the microphone.
+    fn select_input(&mut self, source: InselV, line: InVoldB) {
+        let mut profile = self.power_profile();
+        match source {
+            InselV::Line => profile.line_in = true,
+            InselV::Mic => profile.mic = true,
+        }
+        self.write_power_profile(profile);
+        let mut path = self.analogue_audio_path;
+        path.set_insel(source);
+        if source == InselV::Mic {
+            path.set_micboost(self.input_gains.micboost);
+        }
+        if path != self.analogue_audio_path {
+            self.analogue_audio_path = path;
+            self.interface.write(path.to_frame());
+        }
+        if source == InselV::Line && self.both_invol() != (line, line) {
+            self.set_both_invol(line);
+        }
+    }
+
+    /// Unmute the selected ADC input. With the microphone selected, line inputs get back
+    /// `line_muted` if `BYPASS` is set, and stay muted otherwise.
+    fn unmute_adc_input(&mut self, line_muted: (bool, bool)) {
+        match self.insel() {
+            InselV::Line => {
+                if self.both_inmute() != (false, false) {
+                    self.set_both_inmute(false);
+                }
+            }
+            InselV::Mic => {
+                if self.mutemic() {
+                    self.set_mutemic(false);
+                }
+                if !self.bypass() || self.both_inmute() == line_muted {
+                    return;
+                }
+                if line_muted.0 == line_muted.1 {
+                    self.set_both_inmute(line_muted.0);
+                } else {
+                    self.set_left_inmute(line_muted.0);
+                    self.set_right_inmute(line_muted.1);
+                }
+            }
+        }
+    }
+}
+
+/// Headphone volume limit.
+///
+/// Monitoring cap apply while `BYPASS` or `SIDETONE` is set. Volumes are lowered before those
+/// paths are enabled.
+impl<I> Wm8731<I>
+where
+    I: WriteFrame,
+{
+    pub fn volume_limit(&self) -> Option<VolumeLimit> {
+        self.volume_limit
+    }
+
+    /// Set or remove the volume limit. Current volumes are lowered if they exceed it.
+    pub fn set_volume_limit(&mut self, value: Option<VolumeLimit>) {
+        self.volume_limit = value;
+        self.enforce_volume_limit();
+    }
+
+    /// Highest headphone volume currently allowed. `None` if there is no limit.
+    pub fn hpvol_cap(&self) -> Option<HpVoldB> {
+        let monitoring = self.monitoring();
+        self.volume_limit.map(|limit| limit.cap(monitoring))
+    }
+
+    /// Returns `true` if a headphone volume was lowered by the limit since the last call to
+    /// [`Wm8731::clear_limit_hit`].
+    pub fn limit_hit(&self) -> bool {
+        self.limit_hit
+    }
+
+    pub fn clear_limit_hit(&mut self) {
+        self.limit_hit = false;
+    }
+
+    fn monitoring(&self) -> bool {
+        self.analogue_audio_path.bypass() || self.analogue_audio_path.sidetone()
+    }
+
+    fn limit_hpvol(&mut self, volume: HpVoldB) -> HpVoldB {
+        let monitoring = self.monitoring();
+        match self.volume_limit {
+            Some(limit) => {
+                let (volume, hit) = limit.clamp(volume, monitoring);
+                self.limit_hit |= hit;
+                volume
+            }
+            None => volume,
+        }
+    }
+
+    /// Clamp stored headphone volumes without writing them. Return `true` if one was lowered.
+    fn clamp_hpvol_shadow(&mut self) -> bool {
+        let (left, right) = self.both_hpvol();
+        self.left_hpvol = self.limit_hpvol(left);
+        self.right_hpvol = self.limit_hpvol(right);
+        (left, right) != self.both_hpvol()
+    }
+
+    /// Lower and write headphone volumes exceeding the limit.
+    fn enforce_volume_limit(&mut self) {
+        let (left, right) = self.both_hpvol();
+        if !self.clamp_hpvol_shadow() {
+            return;
+        }
+        let (new_left, new_right) = self.both_hpvol();
+        if new_left == new_right {
+            self.set_both_hpvol(new_left, false);
+        } else if new_left != left {
+            self.set_left_hpvol(new_left, false);
+            if new_right != right {
+                self.set_right_hpvol(new_right, false);
+            }
+        } else {
+            self.set_right_hpvol(new_right, false);
+        }
+    }
+}
+
+/// Standby and resume.
+impl<I> Wm8731<I>
+where
+    I: WriteFrame,
+{
+    /// Get a copy of every register content stored in the driver.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            left_line_in: self.left_line_in,
+            right_line_in: self.right_line_in,
+            left_hpvol: self.left_hpvol,
+            right_hpvol: self.right_hpvol,
+            analogue_audio_path: self.analogue_audio_path,
+            digital_audio_path: self.digital_audio_path,
+            power_down: self.power_down,
+            digital_audio_interface: self.digital_audio_interface,
+            sampling: self.sampling,
+            active: self.active,
+        }
+    }
+
+    /// Returns `true` if the codec is suspended.
+    pub fn is_suspended(&self) -> bool {
+        self.suspended.is_some()
+    }
+
+    /// Enter minimum current standby, recording the current configuration.
+    ///
+    /// Headphone outputs are muted, then the [`Wm8731::power_down`] sequence is applied. Nothing
+    /// is done if the codec is already suspended.
+    pub fn suspend<D>(&mut self, delay: &mut D)
+    where
+        D: DelayMs<u16>,
+    {
+        if self.suspended.is_some() {
+            return;
+        }
+        self.suspended = Some(self.snapshot());
+        self.set_both_hpvol(HpVoldB::MUTE, false);
+        self.power_down(delay);
+    }
+
+    /// Leave standby, restoring exactly the configuration recorded by [`Wm8731::suspend`].
+    ///
+    /// The configuration is restored following the [`Wm8731::power_up`] sequence. The digital
+    /// audio interface is activated only if it was active. Return `false` if the codec was not
+    /// suspended.
+    pub fn resume<D>(&mut self, delay: &mut D) -> bool
+    where
+        D: DelayMs<u16>,
+    {
+        let snapshot = match self.suspended.take() {
+            Some(snapshot) => snapshot,
+            None => return false,
+        };
+        self.left_line_in = snapshot.left_line_in;
+        self.right_line_in = snapshot.right_line_in;
+        self.left_hpvol = snapshot.left_hpvol;
+        self.right_hpvol = snapshot.right_hpvol;
+        self.analogue_audio_path = snapshot.analogue_audio_path;
+        self.digital_audio_path = snapshot.digital_audio_path;
+        self.power_down = snapshot.power_down;
+        self.digital_audio_interface = snapshot.digital_audio_interface;
+        self.sampling = snapshot.sampling;
+        self.power_up_sequence(snapshot.active.get(), delay);
+        true
+    }
+}
+
+/// Clock configuration. Value stored only if inactive.
+impl<I> Wm8731<I>
+where
+    I: WriteFrame,
+{
+    /// Get clock configuration. `None` if it was never set with [`Wm8731::set_clock_config`].
+    ///
+    /// Reflect the actual `OSCPD`, `CLKOUTPD`, `CLKIDIV2` and `CLKODIV2` fields content, so it stay
+    /// accurate if those fields are modified individually.
+    pub fn clock_config(&self) -> Option<ClockConfig> {
+        let source = match self.clock_source? {
+            ClockSource::Crystal { hz } | ClockSource::ExternalMclk { hz } if self.oscpd() => {
+                ClockSource::ExternalMclk { hz }
+            }
+            ClockSource::Crystal { hz } | ClockSource::ExternalMclk { hz } => {
+                ClockSource::Crystal { hz }
+            }
+        };
+        let clkout = match (self.clkoutpd(), self.clkodiv2()) {
+            (true, _) => None,
+            (false, false) => Some(ClkoutDiv::Div1),
+            (false, true) => Some(ClkoutDiv::Div2),
+        };
+        Some(
+            ClockConfig::new(source)
+                .with_core_div2(self.clkidiv2())
+                .with_clkout(clkout),
+        )
+    }
+
+    /// Set `OSCPD`, `CLKOUTPD`, `CLKIDIV2` and `CLKODIV2` consistently with a clock configuration.
+    ///
+    /// Nothing is changed when the device is active, because `CLKIDIV2` and `CLKODIV2` can't be
+    /// modified in this state.
+    pub fn set_clock_config(&mut self, value: ClockConfig) {
+        if !self.active.get() {
+            self.clock_source = Some(value.source);
+            self.sampling.set_clkidiv2(value.clkidiv2());
+            self.sampling.set_clkodiv2(value.clkodiv2());
+            self.power_down.set_oscpd(value.oscpd());
+            self.power_down.set_clkoutpd(value.clkoutpd());
+            self.interface.write(self.power_down.to_frame());
+        }
+    }
+
+    /// Core clock frequency. `None` if clock configuration is unknown.
+    pub fn core_clock_hz(&self) -> Option<u32> {
+        self.clock_config().map(|clock| clock.core_clock_hz())
+    }
+
+    /// CLKOUT frequency. `None` if clock configuration is unknown or if CLKOUT is powered down.
+    pub fn clkout_hz(&self) -> Option<u32> {
+        self.clock_config().and_then(|clock| clock.clkout_hz())
+    }
+
+    /// ADC sampling frequency. `None` if clock configuration is unknown.
+    pub fn adc_rate_hz(&self) -> Option<u32> {
+        self.core_clock_hz()
+            .map(|hz| self.sampling_rates().adc_rate(hz))
+    }
+
+    /// DAC sampling frequency. `None` if clock configuration is unknown.
+    pub fn dac_rate_hz(&self) -> Option<u32> {
+        self.core_clock_hz()
+            .map(|hz| self.sampling_rates().dac_rate(hz))
+    }
+}
+
+/// Supply current estimation.
+impl<I> Wm8731<I>
+where
+    I: WriteFrame,
+{
+    /// Describe everything affecting current consumption.
+    ///
+    /// MCLK is assumed running when it comes from an external device, or when the oscillator is
+    /// powered if the clock configuration is a crystal or is unknown. The sampling frequency is
+    /// the highest of ADC and DAC ones, unknown without a clock configuration.
+    pub fn operating_point(&self) -> OperatingPoint {
+        let sample_rate_hz = match (self.adc_rate_hz(), self.dac_rate_hz()) {
+            (Some(adc), Some(dac)) => Some(adc.max(dac)),
+            (adc, dac) => adc.or(dac),
+        };
+        OperatingPoint {
+            profile: self.power_profile(),
+            poweroff: self.poweroff(),
+            oscillator: !self.oscpd(),
+            clkout: !self.clkoutpd(),
+            clock_running: match self.clock_source {
+                Some(ClockSource::ExternalMclk { .. }) => true,
+                _ => !self.oscpd(),
+            },
+            master: self.ms() == MsV::Master,
+            sample_rate_hz,
+        }
+    }
+
+    /// Current drawn on each supply rail with the current configuration, estimated from the
+    /// datasheet tables, see [`current`] module.
+    pub fn estimated_current(&self, voltage: SupplyVoltage) -> SupplyCurrent {
+        CurrentTable::datasheet(voltage).estimate(&self.operating_point())
+    }
+}
+
+/// Configuration diagnostics.
+impl<I> Wm8731<I>
+where
+    I: WriteFrame,
+{
+    /// Inspect the configuration stored in the driver and report inconsistencies.
+    ///
+    /// De-emphasis is checked only when the clock configuration is known.
+    pub fn diagnose(&self) -> Diagnostics {
+        let mut diag = Diagnostics::new();
+        let path = &self.analogue_audio_path;
+        let pd = &self.power_down;
+        let active = self.active.get();
+        if path.dacsel() && pd.dacpd() {
+            diag.push(Warning::DacRoutedButPoweredDown);
+        }
+        if path.dacsel() && active && self.digital_audio_path.dacmu() {
+            diag.push(Warning::DacRoutedButMuted);
+        }
+        if !pd.adcpd() {
+            match path.insel() {
+                InselV::Line if pd.lineinpd() => diag.push(Warning::LineInSelectedButPoweredDown),
+                InselV::Line if self.left_line_in.mute() && self.right_line_in.mute() => {
+                    diag.push(Warning::LineInSelectedButMuted)
+                }
+                InselV::Mic if pd.micpd() => diag.push(Warning::MicSelectedButPoweredDown),
+                InselV::Mic if path.mutemic() => diag.push(Warning::MicSelectedButMuted),
+                _ => (),
+            }
+        }
+        if path.bypass() && pd.lineinpd() {
+            diag.push(Warning::BypassButLineInPoweredDown);
+        }
+        if path.sidetone() && pd.micpd() {
+            diag.push(Warning::SidetoneButMicPoweredDown);
+        }
+        if (path.dacsel() || path.bypass() || path.sidetone()) && pd.outpd() {
+            diag.push(Warning::RoutedToPoweredDownOutputs);
+        }
+        let deemp_rate = match self.digital_audio_path.deemp() {
+            DeempV::Disable => None,
+            DeempV::F32k => Some(32_000),
+            DeempV::F44k1 => Some(44_100),
+            DeempV::F48k => Some(48_000),
+        };
+        if let (Some(expected), Some(actual)) = (deemp_rate, self.dac_rate_hz()) {
+            // accept 1% error
+            if actual.abs_diff(expected) > expected / 100 {
+                diag.push(Warning::DeemphasisRateMismatch);
+            }
+        }
+        if !pd.dacpd() && !self.sampling_rates().soft_mute_supported() {
+            diag.push(Warning::SoftMuteUnsupported);
+        }
+        if !pd.outpd() && path.dacsel() && !active {
+            diag.push(Warning::OutputsPoweredWhileInactive);
+        }
+        if self.audio_format().is_err() {
+            diag.push(Warning::InvalidAudioFormat);
+        }
+        diag
+    }
+}
+
+/// Digital audio link description.
+impl<I> Wm8731<I>
+where
+    I: WriteFrame,
+{
+    /// Describe the digital audio link as seen by the MCU, to configure its I2S/SAI peripheral.
+    ///
+    /// `mclk_hz` is the frequency of the clock on the XTI/MCLK pin. Fail if the Digital Audio
+    /// Interface Format fields contain a forbidden combination.
+    pub fn link_descriptor(&self, mclk_hz: u32) -> Result<LinkDescriptor, AudioFormatError> {
+        let core_clock_hz = if self.sampling.clkidiv2() {
+            mclk_hz / 2
+        } else {
+            mclk_hz
+        };
+        Ok(LinkDescriptor::new(
+            self.audio_format()?,
+            self.ms(),
+            self.lrswap(),
+            self.sampling_rates(),
+            core_clock_hz,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interface::mock::{FrameLog, NoDelay};
+
+    #[test]
+    fn power_sequence_test() {
+        let mut codec = Wm8731::new(FrameLog::new());
+        codec.interface.clear();
+        let mut delay = NoDelay::default();
+        codec.power_up(PowerProfile::ALL, &mut delay);
+        let frames = codec.interface.frames();
+        // power everything except outputs first, outputs last, after activation.
+        assert_eq!(frames[0], 0b110 << 9 | 0b0001_0000);
+        assert_eq!(frames[frames.len() - 2], 0b1001 << 9 | 1);
+        assert_eq!(frames[frames.len() - 1], 0b110 << 9);
+        assert!(delay.total_ms >= power::VMID_SETTLE_MS as u32);
+
+        codec.set_dacmu(false);
+        codec.interface.clear();
+        codec.power_down(&mut delay);
+        let frames = codec.interface.frames();
+        assert_eq!(frames[0], 0b101 << 9 | 0b1000);
+        assert_eq!(frames[1], 0b110 << 9 | 0b0001_0000);
+        assert_eq!(frames[2], 0b1001 << 9);
+        assert_eq!(frames[3], 0b110 << 9 | 0b1001_1111);
+        assert!(!codec.dacmu());
+    }
+
+    #[test]
+    fn use_case_test() {
+        let mut codec = Wm8731::new(FrameLog::new());
+        codec.power_up_use_case(UseCase::Playback, &mut NoDelay::default());
+        assert_eq!(codec.power_profile(), UseCase::Playback.power_profile());
+        assert!(codec.dacsel() && !codec.bypass());
+
+        codec.interface.clear();
+        codec.set_use_case(UseCase::RecordMic);
+        assert_eq!(codec.power_profile(), UseCase::RecordMic.power_profile());
+        assert_eq!(codec.insel(), InselV::Mic);
+        // mic and ADC powered before routing, DAC and outputs powered down after.
+        let frames = codec.interface.frames();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0] & 0b1_1111, 0b0_0001);
+        assert_eq!(frames[2] & 0b1_1111, 0b1_1001);
+    }
+
+    #[test]
+    fn record_use_case_test() {
+        let mut codec = Wm8731::new(FrameLog::new());
+        codec.power_up_use_case(UseCase::RecordMic, &mut NoDelay::default());
+        // the microphone is still muted at ADC input
+        assert!(codec.mutemic());
+        assert!(!codec.is_connected(Source::Mic, Sink::Adc));
+        codec.set_mutemic(false);
+        assert!(codec.is_connected(Source::Mic, Sink::Adc));
+
+        codec.set_use_case(UseCase::RecordLine);
+        assert_eq!(codec.both_inmute(), (true, true));
+        assert!(!codec.is_connected(Source::LineIn, Sink::Adc));
+        codec.set_both_inmute(false);
+        assert!(codec.is_connected(Source::LineIn, Sink::Adc));
+    }
+
+    #[test]
+    fn suspend_resume_test() {
+        let mut delay = NoDelay::default();
+        let mut codec = Wm8731::new(FrameLog::new());
+        codec.set_both_hpvol(HpVoldB::N12DB, true);
+        codec.set_left_invol(InVoldB::P3DB);
+        codec.power_up_use_case(UseCase::Duplex, &mut delay);
+        let before = codec.snapshot();
+        codec.suspend(&mut delay);
+        assert!(codec.is_suspended());
+        assert!(codec.poweroff());
+        assert!(!codec.is_active());
+        assert_eq!(codec.left_hpvol(), HpVoldB::MUTE);
+        codec.interface.clear();
+        assert!(codec.resume(&mut delay));
+        assert_eq!(codec.snapshot(), before);
+        // outputs powered last
+        let frames = codec.interface.frames();
+        assert_eq!(frames[frames.len() - 1], 0b110 << 9 | 0b10);
+        assert!(!codec.resume(&mut delay));
+    }
+
+    #[test]
+    fn volume_limit_test() {
+        let mut codec = Wm8731::new(FrameLog::new());
+        codec.set_bypass(false);
+        codec.set_both_hpvol(HpVoldB::P6DB, false);
+        let limit = VolumeLimit::new(HpVoldB::N6DB).with_monitor_max(HpVoldB::N20DB);
+        codec.interface.clear();
+        codec.set_volume_limit(Some(limit));
+        assert_eq!(
+            codec.interface.frames(),
+            &[0b10 << 9 | 1 << 8 | HpVoldB::N6DB.into_raw() as u16]
+        );
+        assert!(codec.limit_hit());
+        codec.clear_limit_hit();
+        codec.set_left_hpvol(HpVoldB::N10DB, false);
+        assert!(!codec.limit_hit());
+        // volume lowered before bypass is enabled
+        codec.interface.clear();
+        codec.set_bypass(true);
+        assert_eq!(
+            codec.interface.frames(),
+            &[
+                0b10 << 9 | 1 << 8 | HpVoldB::N20DB.into_raw() as u16,
+                0b100 << 9 | 0b1010
+            ]
+        );
+        assert_eq!(codec.hpvol_cap(), Some(HpVoldB::N20DB));
+        codec.set_right_hpvol(HpVoldB::P6DB, false);
+        assert_eq!(codec.right_hpvol(), HpVoldB::N20DB);
+        assert!(codec.limit_hit());
+        codec.set_volume_limit(None);
+        codec.set_right_hpvol(HpVoldB::P6DB, false);
+        assert_eq!(codec.right_hpvol(), HpVoldB::P6DB);
+
+        // limit survives a reset and applies to the default 0dB volumes
+        let limit = VolumeLimit::new(HpVoldB::N6DB);
+        codec.set_volume_limit(Some(limit));
+        codec.interface.clear();
+        codec.reset();
+        assert_eq!(codec.both_hpvol(), (HpVoldB::N6DB, HpVoldB::N6DB));
+        codec.set_outpd(false);
+        codec.connect(Source::Dac, Sink::Outputs).unwrap();
+        let cap = HpVoldB::N6DB.into_raw() as u16;
+        let hp_frames = codec
+            .interface
+            .frames()
+            .iter()
+            .filter(|&&frame| matches!(frame >> 9, 0b10 | 0b11));
+        assert_eq!(hp_frames.clone().count(), 1);
+        assert!(hp_frames.clone().all(|frame| frame & 0x7f <= cap));
+        assert!(!codec.outpd() && codec.left_hpvol().into_raw() as u16 <= cap);
+    }
+
+    #[test]
+    fn routing_test() {
+        let mut codec = Wm8731::new(FrameLog::new());
+        codec.disconnect(Source::LineIn, Sink::Outputs).unwrap();
+        assert!(!codec.bypass());
+        assert_eq!(codec.routes().count(), 0);
+        assert_eq!(
+            codec.connect(Source::Dac, Sink::Adc),
+            Err(RoutingError {
+                source: Source::Dac,
+                sink: Sink::Adc
+            })
+        );
+        codec.set_both_hpvol(HpVoldB::N6DB, false);
+        codec.connect(Source::Dac, Sink::Outputs).unwrap();
+        assert!(codec.dacsel() && !codec.dacpd() && !codec.outpd() && !codec.dacmu());
+        codec.connect(Source::LineIn, Sink::Adc).unwrap();
+        assert!(!codec.lineinpd() && !codec.adcpd());
+        assert_eq!(codec.both_inmute(), (false, false));
+        let routes: [Option<Route>; 2] = {
+            let mut routes = codec.routes();
+            [routes.next(), routes.next()]
+        };
+        assert_eq!(
+            routes[0],
+            Some(Route {
+                source: Source::LineIn,
+                sink: Sink::Adc,
+                left: Some(Centibels::ZERO),
+                right: Some(Centibels::ZERO),
+            })
+        );
+        assert_eq!(routes[1].and_then(|route| route.left), Some(Centibels(-60)));
+        // the ADC input is replaced, line input is no longer used
+        codec.connect(Source::Mic, Sink::Adc).unwrap();
+        assert_eq!(codec.insel(), InselV::Mic);
+        assert!(!codec.mutemic() && !codec.micpd() && codec.lineinpd());
+        assert!(!codec.is_connected(Source::LineIn, Sink::Adc));
+        codec.set_micboost(true);
+        codec.set_sideatt(SideAttdB::N9DB);
+        codec.connect(Source::Mic, Sink::Outputs).unwrap();
+        let sidetone = codec
+            .routes()
+            .find(|route| route.source == Source::Mic && route.sink == Sink::Outputs);
+        assert_eq!(sidetone.and_then(|route| route.right), Some(Centibels(50)));
+        codec.disconnect(Source::Mic, Sink::Adc).unwrap();
+        assert!(codec.mutemic() && codec.adcpd() && !codec.micpd());
+        codec.disconnect(Source::Mic, Sink::Outputs).unwrap();
+        codec.disconnect(Source::Dac, Sink::Outputs).unwrap();
+        assert_eq!(codec.power_profile(), PowerProfile::NONE);
+        assert_eq!(codec.routes().count(), 0);
+    }
+
+    #[test]
+    fn switch_input_test() {
+        let mut codec = Wm8731::new(FrameLog::new());
+        codec.power_up_use_case(UseCase::RecordLine, &mut NoDelay::default());
+        codec.set_bypass(true);
+        codec.set_both_inmute(false);
+        codec.set_input_gains(InputGains {
+            line: InVoldB::P6DB,
+            micboost: true,
+        });
+        codec.interface.clear();
+        let mut delay = NoDelay::default();
+        codec.switch_input(InselV::Mic, &mut delay);
+        assert!(delay.total_ms >= (input::INPUT_MUTE_MS + input::INPUT_SETTLE_MS) as u32);
+        let frames = codec.interface.frames();
+        // mute line inputs (mic is muted at reset), power mic, select it with MICBOOST, unmute mic
+        // and restore line inputs
+        assert_eq!(frames.len(), 5);
+        assert_eq!(frames[0], 0b1_1001_0111);
+        assert_eq!(frames[1] >> 9, 0b110);
+        assert_eq!(frames[1] & 0b10, 0);
+        assert_eq!(frames[2] & 0b111, 0b111);
+        assert_eq!(frames[3] & 0b111, 0b101);
+        assert_eq!(frames[4], 0b1_0001_0111);
+        assert!(codec.micboost() && !codec.mutemic());
+        // line inputs still feed outputs
+        assert_eq!(codec.both_inmute(), (false, false));
+        assert_eq!(codec.both_invol(), (InVoldB::Z0DB, InVoldB::Z0DB));
+
+        codec.switch_input(InselV::Line, &mut delay);
+        assert_eq!(codec.insel(), InselV::Line);
+        assert!(codec.mutemic() && codec.micboost());
+        assert_eq!(codec.both_inmute(), (false, false));
+        assert_eq!(codec.both_invol(), (InVoldB::P6DB, InVoldB::P6DB));
+
+        codec.reset();
+        assert_eq!(codec.input_gains(), InputGains::DEFAULT);
+    }
+
+    #[test]
+    fn calibrate_dc_offset_test() {
+        let mut codec = Wm8731::new(FrameLog::new());
+        let mut delay = NoDelay::default();
+        assert!(!codec.calibrate_dc_offset(&mut delay));
+        codec.power_up_use_case(UseCase::RecordLine, &mut delay);
+        codec.set_both_inmute(false);
+        codec.interface.clear();
+        delay.total_ms = 0;
+        assert!(codec.calibrate_dc_offset(&mut delay));
+        assert_eq!(delay.total_ms, input::HPF_SETTLE_MS as u32);
+        let frames = codec.interface.frames();
+        // filter enabled while muted, frozen before unmuting
+        assert_eq!(frames[0] >> 7, 0b11);
+        assert_eq!(frames[1], 0b101 << 9 | 0b1000);
+        assert_eq!(frames[2], 0b101 << 9 | 0b1_1001);
+        assert_eq!(frames[3] >> 7, 0b0);
+        assert_eq!(codec.adc_high_pass(), AdcHighPass::FrozenOffset);
+        assert!(codec.adchpd() && codec.hpor());
+        assert_eq!(codec.both_inmute(), (false, false));
+        codec.set_adc_high_pass(AdcHighPass::Disabled);
+        assert!(codec.adchpd() && !codec.hpor());
+        codec.set_adc_high_pass(AdcHighPass::Enabled);
+        assert_eq!(codec.adc_high_pass(), AdcHighPass::Enabled);
+    }
+
+    #[test]
+    fn estimated_current_test() {
+        let mut codec = Wm8731::new(FrameLog::new());
+        // reset state: POWEROFF, clock unknown but the oscillator powered
+        assert_eq!(
+            codec.estimated_current(SupplyVoltage::V3_3),
+            SupplyCurrent::new(200, 300, 77_000, 65_000)
+        );
+        codec.set_clock_config(ClockConfig::new(ClockSource::ExternalMclk {
+            hz: 12_288_000,
+        }));
+        codec.power_up_use_case(UseCase::Playback, &mut NoDelay::default());
+        // datasheet Table 1, playback only using external MCLK
+        assert_eq!(
+            codec.estimated_current(SupplyVoltage::V3_3),
+            SupplyCurrent::new(3_300_000, 1_700_000, 2_300_000, 70_000)
+        );
+        // 8kHz, converters digital current scaled from 48kHz
+        codec.deactivate();
+        codec.set_sampling_rates(SamplingRates::ADC1536_DAC1536_A);
+        assert_eq!(
+            codec.estimated_current(SupplyVoltage::V3_3),
+            SupplyCurrent::new(3_300_000, 1_700_000, 447_500, 70_000)
+        );
+        codec.set_sampling_rates(SamplingRates::ADC256_DAC256_A);
+        // master mode drives BCLK and LRC
+        codec.set_ms(MsV::Master);
+        assert_eq!(
+            codec.estimated_current(SupplyVoltage::V3_3),
+            SupplyCurrent::new(3_300_000, 1_700_000, 2_300_000, 230_000)
+        );
+        codec.set_ms(MsV::Slave);
+        codec.activate();
+        // use cases not in the datasheet, from the nearest mode without CLKOUT
+        codec.set_use_case(UseCase::RecordLine);
+        assert_eq!(
+            codec.estimated_current(SupplyVoltage::V3_3),
+            SupplyCurrent::new(9_200_000, 0, 2_600_000, 70_000)
+        );
+        codec.set_use_case(UseCase::Bypass);
+        assert_eq!(
+            codec.estimated_current(SupplyVoltage::V3_3),
+            SupplyCurrent::new(2_100_000, 1_700_000, 80_000, 70_000)
+        );
+        codec.power_down(&mut NoDelay::default());
+        assert_eq!(
+            codec.estimated_current(SupplyVoltage::V3_3),
+            SupplyCurrent::new(200, 300, 77_000, 65_000)
+        );
+    }
+
+    #[test]
+    fn diagnose_test() {
+        let mut codec = Wm8731::new(FrameLog::new());
+        codec.set_dacsel(true);
+        codec.set_sidetone(true);
+        let diag = codec.diagnose();
+        assert!(diag.contains(Warning::DacRoutedButPoweredDown));
+        assert!(diag.contains(Warning::SidetoneButMicPoweredDown));
+        assert!(diag.contains(Warning::RoutedToPoweredDownOutputs));
+        // MUTEMIC only mutes the microphone to the ADC, sidetone is still heard
+        codec.set_micpd(false);
+        assert!(codec.mutemic());
+        assert!(!codec
+            .diagnose()
+            .contains(Warning::SidetoneButMicPoweredDown));
+
+        let mut codec = Wm8731::new(FrameLog::new());
+        codec.set_sampling_rates(SamplingRates::ADC256_DAC256_A);
+        codec.set_clock_config(ClockConfig::new(ClockSource::ExternalMclk {
+            hz: 12_288_000,
+        }));
+        codec.set_deemp(DeempV::F48k);
+        codec.set_sidetone(false);
+        codec.power_up_use_case(UseCase::Playback, &mut NoDelay::default());
+        codec.set_dacmu(false);
+        assert!(codec.diagnose().is_empty(), "{:?}", codec.diagnose());
+        codec.set_deemp(DeempV::F44k1);
+        assert!(codec.diagnose().contains(Warning::DeemphasisRateMismatch));
+    }
+}
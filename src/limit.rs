@@ -0,0 +1,70 @@
+//! Headphone output level limit.
+//!
+//! Once a [`VolumeLimit`] is set with
+//! [`Wm8731::set_volume_limit`](crate::Wm8731::set_volume_limit), every headphone volume write is
+//! clamped, whatever its origin: direct setters, fades, stereo controllers or power sequences.
+//! Monitoring paths (`BYPASS` and `SIDETONE`) add analogue signal on top of playback, so they can
+//! have their own lower cap.
+use crate::HpVoldB;
+
+/// Maximum headphone volumes.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct VolumeLimit {
+    /// Cap in every situation.
+    pub max: HpVoldB,
+    /// Cap while `BYPASS` or `SIDETONE` is set. Only effective if lower than `max`.
+    pub monitor_max: Option<HpVoldB>,
+}
+
+impl VolumeLimit {
+    /// Limit without specific monitoring cap.
+    pub const fn new(max: HpVoldB) -> Self {
+        Self {
+            max,
+            monitor_max: None,
+        }
+    }
+
+    pub const fn with_monitor_max(self, monitor_max: HpVoldB) -> Self {
+        Self {
+            monitor_max: Some(monitor_max),
+            ..self
+        }
+    }
+
+    /// Cap to apply, `monitoring` being `true` when `BYPASS` or `SIDETONE` is set.
+    pub fn cap(&self, monitoring: bool) -> HpVoldB {
+        match self.monitor_max {
+            Some(monitor_max) if monitoring && monitor_max.into_raw() < self.max.into_raw() => {
+                monitor_max
+            }
+            _ => self.max,
+        }
+    }
+
+    /// Clamp a volume to the cap. Return the clamped volume and `true` if it was lowered.
+    pub fn clamp(&self, volume: HpVoldB, monitoring: bool) -> (HpVoldB, bool) {
+        let cap = self.cap(monitoring);
+        if volume.into_raw() > cap.into_raw() {
+            (cap, true)
+        } else {
+            (volume, false)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clamp_test() {
+        let limit = VolumeLimit::new(HpVoldB::N6DB).with_monitor_max(HpVoldB::N20DB);
+        assert_eq!(limit.clamp(HpVoldB::P6DB, false), (HpVoldB::N6DB, true));
+        assert_eq!(limit.clamp(HpVoldB::N10DB, false), (HpVoldB::N10DB, false));
+        assert_eq!(limit.clamp(HpVoldB::N10DB, true), (HpVoldB::N20DB, true));
+        assert_eq!(limit.clamp(HpVoldB::MUTE, true), (HpVoldB::MUTE, false));
+        let limit = VolumeLimit::new(HpVoldB::N30DB).with_monitor_max(HpVoldB::N20DB);
+        assert_eq!(limit.cap(true), HpVoldB::N30DB);
+    }
+}
@@ -0,0 +1,225 @@
+//! Description of the digital audio link, from the point of view of the other side.
+//!
+//! The MCU I2S/SAI peripheral connected to the codec must be configured consistently with the
+//! codec Digital Audio Interface Format and Sampling Control. [`LinkDescriptor`] gather what the
+//! other side need to know in a neutral way, so its configuration can be derived from the codec
+//! configuration instead of being duplicated.
+use crate::{AudioFormat, AudioStandard, DspMode, MsV, SamplingRates};
+
+/// Frame standard of the link.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum LinkStandard {
+    /// Philips I2S standard.
+    I2s,
+    /// MSB justified standard.
+    LeftJustified,
+    /// LSB justified standard.
+    RightJustified,
+    /// PCM/DSP standard with a short frame sync pulse. `data_delay` is the number of bit clock
+    /// cycles between the frame sync rising edge and the MSB.
+    Pcm { data_delay: u8 },
+}
+
+/// Bit clock edge on which data are sampled by the receiver.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum ClockPolarity {
+    /// Data are sampled on bit clock rising edge, and change on falling edge.
+    SampleOnRising,
+    /// Data are sampled on bit clock falling edge, and change on rising edge.
+    SampleOnFalling,
+}
+
+/// Which side of the link generate bit and left/right clocks.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum LinkMaster {
+    /// The codec generate clocks, the host is slave.
+    Codec,
+    /// The host (MCU) generate clocks, the codec is slave.
+    Host,
+}
+
+/// Neutral description of the digital audio link.
+///
+/// `channel_bits` is the number of bit clock cycles per channel. When the codec is master, it
+/// is imposed by the codec clock generator, otherwise it is the smallest usual channel length
+/// able to hold a data word.
+///
+/// ADC and DAC share the bit clock. When the codec is master with different ADC and DAC rates,
+/// ADC frames are longer or shorter than DAC frames, so their channel length is given apart in
+/// `adc_channel_bits`. Otherwise both are equal.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct LinkDescriptor {
+    pub standard: LinkStandard,
+    pub data_bits: u8,
+    /// Number of bit clock cycles per DAC channel.
+    pub channel_bits: u16,
+    /// Number of bit clock cycles per ADC channel.
+    pub adc_channel_bits: u16,
+    pub clock_polarity: ClockPolarity,
+    /// `true` when the right channel is sent while the left/right clock is low.
+    pub lrclk_inverted: bool,
+    /// `true` when the codec swap left and right DAC channels.
+    pub dac_channels_swapped: bool,
+    pub master: LinkMaster,
+    /// Expected DAC left/right clock frequency.
+    pub dac_frame_rate_hz: u32,
+    /// Expected ADC left/right clock frequency.
+    pub adc_frame_rate_hz: u32,
+}
+
+impl LinkDescriptor {
+    /// Build a descriptor from codec settings. `core_clock_hz` is the codec core clock, i.e. MCLK
+    /// eventually divided by 2 when `CLKIDIV2` is set.
+    pub fn new(
+        format: AudioFormat,
+        ms: MsV,
+        lrswap: bool,
+        sampling_rates: SamplingRates,
+        core_clock_hz: u32,
+    ) -> Self {
+        let standard = match format.standard() {
+            AudioStandard::I2s => LinkStandard::I2s,
+            AudioStandard::LeftJustified => LinkStandard::LeftJustified,
+            AudioStandard::RightJustified => LinkStandard::RightJustified,
+            AudioStandard::Dsp { mode: DspMode::A } => LinkStandard::Pcm { data_delay: 1 },
+            AudioStandard::Dsp { mode: DspMode::B } => LinkStandard::Pcm { data_delay: 0 },
+        };
+        let data_bits = format.iwl().bits();
+        let (master, channel_bits, adc_channel_bits) = match ms {
+            // In master mode, BCLK is MCLK/4, or MCLK in USB mode.
+            MsV::Master => {
+                let bclk_div = if sampling_rates.is_usb() { 1 } else { 4 };
+                (
+                    LinkMaster::Codec,
+                    sampling_rates.dac_divider() / bclk_div / 2,
+                    sampling_rates.adc_divider() / bclk_div / 2,
+                )
+            }
+            MsV::Slave => {
+                let bits = if data_bits == 16 { 16 } else { 32 };
+                (LinkMaster::Host, bits, bits)
+            }
+        };
+        let clock_polarity = if format.bclk_inverted() {
+            ClockPolarity::SampleOnFalling
+        } else {
+            ClockPolarity::SampleOnRising
+        };
+        Self {
+            standard,
+            data_bits,
+            channel_bits,
+            adc_channel_bits,
+            clock_polarity,
+            lrclk_inverted: format.lrclk_inverted(),
+            dac_channels_swapped: lrswap,
+            master,
+            dac_frame_rate_hz: sampling_rates.dac_rate(core_clock_hz),
+            adc_frame_rate_hz: sampling_rates.adc_rate(core_clock_hz),
+        }
+    }
+
+    /// Return `true` when the host must generate bit and left/right clocks.
+    pub fn host_is_master(&self) -> bool {
+        self.master == LinkMaster::Host
+    }
+
+    /// Number of bit clock cycles in a stereo DAC frame.
+    pub fn frame_bits(&self) -> u16 {
+        2 * self.channel_bits
+    }
+
+    /// Number of bit clock cycles in a stereo ADC frame.
+    pub fn adc_frame_bits(&self) -> u16 {
+        2 * self.adc_channel_bits
+    }
+
+    /// Return `true` when ADC and DAC frames have the same length, as required by most host
+    /// peripherals running full duplex.
+    pub fn is_symmetric(&self) -> bool {
+        self.channel_bits == self.adc_channel_bits
+    }
+
+    /// Expected bit clock frequency, based on DAC frame rate.
+    pub fn bit_clock_hz(&self) -> u32 {
+        self.dac_frame_rate_hz * self.frame_bits() as u32
+    }
+
+    /// Return `true` when data words fill DAC channels completely.
+    pub fn is_packed(&self) -> bool {
+        self.data_bits as u16 == self.channel_bits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn descriptor_test() {
+        let link = LinkDescriptor::new(
+            AudioFormat::I2S_16,
+            MsV::Slave,
+            false,
+            SamplingRates::ADC256_DAC256_A,
+            12_288_000,
+        );
+        assert_eq!(link.standard, LinkStandard::I2s);
+        assert_eq!(link.channel_bits, 16);
+        assert!(link.host_is_master());
+        assert_eq!(link.bit_clock_hz(), 48_000 * 32);
+        let link = LinkDescriptor::new(
+            AudioFormat::DSP_A_16,
+            MsV::Master,
+            false,
+            SamplingRates::ADC256_DAC256_A,
+            12_288_000,
+        );
+        assert_eq!(link.standard, LinkStandard::Pcm { data_delay: 1 });
+        assert_eq!(link.channel_bits, 32);
+        assert_eq!(link.bit_clock_hz(), 12_288_000 / 4);
+    }
+
+    #[test]
+    fn long_frame_test() {
+        // 8kHz from 18.432MHz, 576 bit clock cycles per frame
+        let link = LinkDescriptor::new(
+            AudioFormat::I2S_16,
+            MsV::Master,
+            false,
+            SamplingRates::ADC2304_DAC2304_C,
+            18_432_000,
+        );
+        assert_eq!(link.channel_bits, 288);
+        assert_eq!(link.frame_bits(), 576);
+        assert_eq!(link.bit_clock_hz(), 18_432_000 / 4);
+        assert!(link.is_symmetric());
+    }
+
+    #[test]
+    fn asymmetric_rates_test() {
+        // ADC at 48kHz, DAC at 8kHz, sharing the bit clock
+        let link = LinkDescriptor::new(
+            AudioFormat::I2S_16,
+            MsV::Master,
+            false,
+            SamplingRates::ADC256_DAC1536_A,
+            12_288_000,
+        );
+        assert_eq!(link.channel_bits, 192);
+        assert_eq!(link.adc_channel_bits, 32);
+        assert!(!link.is_symmetric());
+        assert_eq!(
+            link.adc_frame_rate_hz * link.adc_frame_bits() as u32,
+            link.bit_clock_hz()
+        );
+        // the host generates a single frame length
+        let link = LinkDescriptor::new(
+            AudioFormat::I2S_16,
+            MsV::Slave,
+            false,
+            SamplingRates::ADC256_DAC1536_A,
+            12_288_000,
+        );
+        assert!(link.is_symmetric());
+    }
+}
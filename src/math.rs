@@ -0,0 +1,239 @@
+//! Fixed point helpers.
+//!
+//! The crate target microcontrollers without FPU, so level computations use integers only.
+//! Logarithms are in Q16 (16 fractional bits), ratios in Q16 where `65536` is unity.
+
+/// 20*log10(2) in thousandths of centibel, ie the gain of one octave of amplitude.
+const MILLI_CB_PER_OCTAVE: i64 = 60_206;
+
+/// Q30 values of 2^(2^-(k+1)), for k in 0..16.
+const EXP2_FRAC: [u64; 16] = [
+    0x5a82799a, 0x4c1bf829, 0x45cae0f2, 0x42d561b4, 0x4166c34c, 0x40b268fa, 0x4058f6a8, 0x402c6be9,
+    0x4016321b, 0x400b1818, 0x40058bce, 0x4002c5d8, 0x400162e8, 0x4000b173, 0x400058b9, 0x40002c5d,
+];
+
+/// Q30 values of sin(x) for x in 0..=π/2, in 256 intervals.
+#[rustfmt::skip]
+const SINE_QUARTER: [u32; 257] = [
+    0x00000000, 0x006487c4, 0x00c90e90, 0x012d936c, 0x0192155f, 0x01f69373,
+    0x025b0caf, 0x02bf801a, 0x0323ecbe, 0x038851a2, 0x03ecadcf, 0x0451004d,
+    0x04b54825, 0x0519845e, 0x057db403, 0x05e1d61b, 0x0645e9af, 0x06a9edc9,
+    0x070de172, 0x0771c3b3, 0x07d59396, 0x08395024, 0x089cf867, 0x09008b6a,
+    0x09640837, 0x09c76dd8, 0x0a2abb59, 0x0a8defc3, 0x0af10a22, 0x0b540982,
+    0x0bb6ecef, 0x0c19b374, 0x0c7c5c1e, 0x0cdee5f9, 0x0d415013, 0x0da39978,
+    0x0e05c135, 0x0e67c65a, 0x0ec9a7f3, 0x0f2b650f, 0x0f8cfcbe, 0x0fee6e0d,
+    0x104fb80e, 0x10b0d9d0, 0x1111d263, 0x1172a0d7, 0x11d3443f, 0x1233bbac,
+    0x1294062f, 0x12f422db, 0x135410c3, 0x13b3cefa, 0x14135c94, 0x1472b8a5,
+    0x14d1e242, 0x1530d881, 0x158f9a76, 0x15ee2738, 0x164c7ddd, 0x16aa9d7e,
+    0x17088531, 0x1766340f, 0x17c3a931, 0x1820e3b0, 0x187de2a7, 0x18daa52f,
+    0x19372a64, 0x19937161, 0x19ef7944, 0x1a4b4128, 0x1aa6c82b, 0x1b020d6c,
+    0x1b5d100a, 0x1bb7cf23, 0x1c1249d8, 0x1c6c7f4a, 0x1cc66e99, 0x1d2016e9,
+    0x1d79775c, 0x1dd28f15, 0x1e2b5d38, 0x1e83e0eb, 0x1edc1953, 0x1f340596,
+    0x1f8ba4dc, 0x1fe2f64c, 0x2039f90f, 0x2090ac4d, 0x20e70f32, 0x213d20e8,
+    0x2192e09b, 0x21e84d76, 0x223d66a8, 0x22922b5e, 0x22e69ac8, 0x233ab414,
+    0x238e7673, 0x23e1e117, 0x2434f332, 0x2487abf7, 0x24da0a9a, 0x252c0e4f,
+    0x257db64c, 0x25cf01c8, 0x261feffa, 0x2670801a, 0x26c0b162, 0x2710830c,
+    0x275ff452, 0x27af0472, 0x27fdb2a7, 0x284bfe2f, 0x2899e64a, 0x28e76a37,
+    0x29348937, 0x2981428c, 0x29cd9578, 0x2a19813f, 0x2a650525, 0x2ab02071,
+    0x2afad269, 0x2b451a55, 0x2b8ef77d, 0x2bd8692b, 0x2c216eaa, 0x2c6a0746,
+    0x2cb2324c, 0x2cf9ef09, 0x2d413ccd, 0x2d881ae8, 0x2dce88aa, 0x2e148566,
+    0x2e5a1070, 0x2e9f291b, 0x2ee3cebe, 0x2f2800af, 0x2f6bbe45, 0x2faf06da,
+    0x2ff1d9c7, 0x30343667, 0x30761c18, 0x30b78a36, 0x30f8801f, 0x3138fd35,
+    0x317900d6, 0x31b88a66, 0x31f79948, 0x32362ce0, 0x32744493, 0x32b1dfc9,
+    0x32eefdea, 0x332b9e5e, 0x3367c090, 0x33a363ec, 0x33de87de, 0x34192bd5,
+    0x34534f41, 0x348cf190, 0x34c61236, 0x34feb0a5, 0x3536cc52, 0x356e64b2,
+    0x35a5793c, 0x35dc0968, 0x361214b0, 0x36479a8e, 0x367c9a7e, 0x36b113fd,
+    0x36e5068a, 0x371871a5, 0x374b54ce, 0x377daf89, 0x37af8159, 0x37e0c9c3,
+    0x3811884d, 0x3841bc7f, 0x387165e3, 0x38a08402, 0x38cf1669, 0x38fd1ca4,
+    0x392a9642, 0x395782d3, 0x3983e1e8, 0x39afb313, 0x39daf5e8, 0x3a05a9fd,
+    0x3a2fcee8, 0x3a596442, 0x3a8269a3, 0x3aaadea6, 0x3ad2c2e8, 0x3afa1605,
+    0x3b20d79e, 0x3b470753, 0x3b6ca4c4, 0x3b91af97, 0x3bb6276e, 0x3bda0bf0,
+    0x3bfd5cc4, 0x3c201994, 0x3c42420a, 0x3c63d5d1, 0x3c84d496, 0x3ca53e09,
+    0x3cc511d9, 0x3ce44fb7, 0x3d02f757, 0x3d21086c, 0x3d3e82ae, 0x3d5b65d2,
+    0x3d77b192, 0x3d9365a8, 0x3dae81cf, 0x3dc905c5, 0x3de2f148, 0x3dfc4418,
+    0x3e14fdf7, 0x3e2d1ea8, 0x3e44a5ef, 0x3e5b9392, 0x3e71e759, 0x3e87a10c,
+    0x3e9cc076, 0x3eb14563, 0x3ec52fa0, 0x3ed87efc, 0x3eeb3347, 0x3efd4c54,
+    0x3f0ec9f5, 0x3f1fabff, 0x3f2ff24a, 0x3f3f9cab, 0x3f4eaafe, 0x3f5d1d1d,
+    0x3f6af2e3, 0x3f782c30, 0x3f84c8e2, 0x3f90c8da, 0x3f9c2bfb, 0x3fa6f228,
+    0x3fb11b48, 0x3fbaa740, 0x3fc395f9, 0x3fcbe75e, 0x3fd39b5a, 0x3fdab1d9,
+    0x3fe12acb, 0x3fe7061f, 0x3fec43c7, 0x3ff0e3b6, 0x3ff4e5e0, 0x3ff84a3c,
+    0x3ffb10c1, 0x3ffd3969, 0x3ffec42d, 0x3fffb10b, 0x40000000,
+];
+
+/// Base 2 logarithm in Q16. Return `i32::MIN` for 0.
+pub fn log2_q16(x: u32) -> i32 {
+    if x == 0 {
+        return i32::MIN;
+    }
+    let int = 31 - x.leading_zeros() as i32;
+    // mantissa in Q31, in [1, 2)
+    let mut m = (x as u64) << (31 - int);
+    let mut frac = 0;
+    for bit in (0..16).rev() {
+        m = (m * m) >> 31;
+        if m >= 2 << 31 {
+            m >>= 1;
+            frac |= 1 << bit;
+        }
+    }
+    (int << 16) | frac
+}
+
+/// Base 2 exponential of a Q16 value, in Q16. Saturate to `u32::MAX`.
+pub fn exp2_q16(x: i32) -> u32 {
+    let int = x >> 16;
+    let frac = x & 0xffff;
+    let mut r: u64 = 1 << 30;
+    for (k, factor) in EXP2_FRAC.iter().enumerate() {
+        if frac & (0x8000 >> k) != 0 {
+            r = (r * factor) >> 30;
+        }
+    }
+    // r is in Q30, result in Q16
+    let shift = int - 14;
+    if shift >= 32 || (shift > 0 && r >> (32 - shift) != 0) {
+        u32::MAX
+    } else if shift >= 0 {
+        (r << shift) as u32
+    } else if shift > -63 {
+        (r >> -shift) as u32
+    } else {
+        0
+    }
+}
+
+/// Amplitude ratio `num / den` in centibels, ie `200 * log10(num / den)`.
+///
+/// Return `i32::MIN` when `num` is 0.
+pub fn ratio_to_centibels(num: u32, den: u32) -> i32 {
+    if num == 0 {
+        return i32::MIN;
+    }
+    let log2 = log2_q16(num) as i64 - log2_q16(den) as i64;
+    let cb = log2 * MILLI_CB_PER_OCTAVE / 1000;
+    ((cb + (1 << 15)) >> 16) as i32
+}
+
+/// Amplitude ratio in Q16 of a gain in centibels, ie `10^(cb / 200)`. Saturate to `u32::MAX`.
+pub fn centibels_to_ratio_q16(cb: i32) -> u32 {
+    let log2 = ((cb as i64 * 1000) << 16).div_euclid(MILLI_CB_PER_OCTAVE);
+    if log2 > i32::MAX as i64 {
+        u32::MAX
+    } else if log2 < i32::MIN as i64 {
+        0
+    } else {
+        exp2_q16(log2 as i32)
+    }
+}
+
+/// Sine in Q31 of a phase where a full turn is `2^32`.
+///
+/// Interpolated from a quarter wave table, error is below -90dB.
+pub fn sin_q31(phase: u32) -> i32 {
+    let quadrant = phase >> 30;
+    // position in the quarter turn, 8 bits of table index and 22 bits of interpolation
+    let mut pos = phase & 0x3fff_ffff;
+    if quadrant & 1 == 1 {
+        pos = 0x4000_0000 - pos;
+    }
+    let index = (pos >> 22) as usize;
+    let frac = (pos & 0x3f_ffff) as u64;
+    let value = if index == 256 {
+        SINE_QUARTER[256] as u64
+    } else {
+        let (a, b) = (SINE_QUARTER[index] as u64, SINE_QUARTER[index + 1] as u64);
+        a + (((b - a) * frac) >> 22)
+    };
+    // Q30 to Q31, saturating the peak
+    let value = (value << 1).min(i32::MAX as u64) as i32;
+    if quadrant >= 2 {
+        -value
+    } else {
+        value
+    }
+}
+
+/// Integer square root, rounded down.
+pub fn isqrt(x: u64) -> u32 {
+    if x == 0 {
+        return 0;
+    }
+    // Newton iterations from a power of two above the root
+    let mut root = 1u64 << (64 - x.leading_zeros()).div_ceil(2);
+    loop {
+        let next = (root + x / root) / 2;
+        if next >= root {
+            return root as u32;
+        }
+        root = next;
+    }
+}
+
+/// Greatest magnitude of a block of samples.
+pub fn peak(samples: &[i32]) -> u32 {
+    samples.iter().map(|s| s.unsigned_abs()).max().unwrap_or(0)
+}
+
+/// Root mean square of a block of samples.
+pub fn rms(samples: &[i32]) -> u32 {
+    if samples.is_empty() {
+        return 0;
+    }
+    let sum: u128 = samples.iter().map(|&s| (s as i64 * s as i64) as u128).sum();
+    isqrt((sum / samples.len() as u128) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn near(value: i64, expected: i64, tolerance: i64) -> bool {
+        (value - expected).abs() <= tolerance
+    }
+
+    #[test]
+    fn log_test() {
+        assert_eq!(log2_q16(1), 0);
+        assert_eq!(log2_q16(1024), 10 << 16);
+        assert!(near(log2_q16(3) as i64, 103_872, 2));
+        assert_eq!(ratio_to_centibels(1, 1), 0);
+        assert_eq!(ratio_to_centibels(1, 10), -200);
+        assert_eq!(ratio_to_centibels(2, 1), 60);
+        assert_eq!(ratio_to_centibels(0, 1), i32::MIN);
+    }
+
+    #[test]
+    fn exp_test() {
+        assert_eq!(exp2_q16(0), 1 << 16);
+        assert_eq!(exp2_q16(3 << 16), 8 << 16);
+        assert_eq!(exp2_q16(-1 << 16), 1 << 15);
+        assert_eq!(exp2_q16(40 << 16), u32::MAX);
+        assert!(near(exp2_q16(1 << 15) as i64, 92_682, 2));
+        assert!(near(centibels_to_ratio_q16(-200) as i64, 6554, 2));
+        assert!(near(centibels_to_ratio_q16(60) as i64, 130_757, 20));
+        assert_eq!(centibels_to_ratio_q16(2000), u32::MAX);
+    }
+
+    #[test]
+    fn sine_test() {
+        assert_eq!(sin_q31(0), 0);
+        assert_eq!(sin_q31(1 << 30), i32::MAX);
+        assert_eq!(sin_q31(1 << 31), 0);
+        assert_eq!(sin_q31(3 << 30), -i32::MAX);
+        // sin(π/6) = 0.5
+        assert!(near(sin_q31(0x1555_5555) as i64, 1 << 30, 1 << 14));
+        assert!(near(sin_q31(0xeaaa_aaab) as i64, -(1 << 30), 1 << 14));
+    }
+
+    #[test]
+    fn level_test() {
+        assert_eq!(isqrt(0), 0);
+        assert_eq!(isqrt(15), 3);
+        assert_eq!(isqrt(16), 4);
+        assert_eq!(isqrt(u64::MAX), u32::MAX);
+        assert_eq!(peak(&[3, -7, 5]), 7);
+        assert_eq!(peak(&[i32::MIN]), 1 << 31);
+        assert_eq!(rms(&[1000, -1000, 1000, -1000]), 1000);
+        assert_eq!(rms(&[]), 0);
+    }
+}
@@ -0,0 +1,375 @@
+//! Level metering and tone analysis.
+//!
+//! [`LevelMeter`] gives peak and RMS levels of interleaved stereo ADC blocks in dBFS, smoothed
+//! with meter ballistics. [`Goertzel`] measures the amplitude of a single frequency and
+//! [`analyze_tone`] estimates level and THD+N of a test tone, so loopback tests can be checked
+//! automatically.
+//!
+//! Samples are sign extended at the interface word length, see
+//! [`SampleLayout`](crate::samples::SampleLayout).
+use crate::db::Centibels;
+use crate::math;
+use crate::IwlV;
+
+/// ln(2) in Q16.
+const LN2_Q16: i64 = 45_426;
+
+/// Meter dynamics.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct Ballistics {
+    /// Time constant when the level rises. 0 follows immediately.
+    pub attack_ms: u32,
+    /// Time constant when the level falls. 0 follows immediately.
+    pub release_ms: u32,
+    /// Time the greatest peak is held.
+    pub hold_ms: u32,
+}
+
+impl Ballistics {
+    /// Raw block levels.
+    pub const INSTANT: Ballistics = Ballistics {
+        attack_ms: 0,
+        release_ms: 0,
+        hold_ms: 0,
+    };
+    /// Slow symmetric response, like a VU meter.
+    pub const VU: Ballistics = Ballistics {
+        attack_ms: 300,
+        release_ms: 300,
+        hold_ms: 0,
+    };
+    /// Immediate attack, slow release and peak hold, like digital peak meters.
+    pub const PEAK: Ballistics = Ballistics {
+        attack_ms: 0,
+        release_ms: 650,
+        hold_ms: 1000,
+    };
+}
+
+impl Default for Ballistics {
+    fn default() -> Self {
+        Ballistics::PEAK
+    }
+}
+
+/// Levels of a channel, in dBFS.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct Level {
+    pub peak: Centibels,
+    pub rms: Centibels,
+    /// Greatest peak over the hold time.
+    pub peak_hold: Centibels,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Default)]
+struct ChannelState {
+    peak: u32,
+    rms: u32,
+    hold: u32,
+    hold_us: u32,
+}
+
+/// Stereo peak and RMS meter.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct LevelMeter {
+    ballistics: Ballistics,
+    sample_rate_hz: u32,
+    full_scale: u32,
+    channels: [ChannelState; 2],
+}
+
+impl LevelMeter {
+    pub fn new(ballistics: Ballistics, sample_rate_hz: u32, iwl: IwlV) -> Self {
+        Self {
+            ballistics,
+            sample_rate_hz: sample_rate_hz.max(1),
+            full_scale: iwl.full_scale(),
+            channels: [ChannelState::default(); 2],
+        }
+    }
+
+    pub fn ballistics(&self) -> Ballistics {
+        self.ballistics
+    }
+
+    pub fn set_ballistics(&mut self, ballistics: Ballistics) {
+        self.ballistics = ballistics;
+    }
+
+    /// Update the sample word length, it must follow the interface configuration.
+    pub fn set_iwl(&mut self, iwl: IwlV) {
+        self.full_scale = iwl.full_scale();
+        self.reset();
+    }
+
+    /// Drop levels back to silence.
+    pub fn reset(&mut self) {
+        self.channels = [ChannelState::default(); 2];
+    }
+
+    /// Measure a block of interleaved stereo samples.
+    pub fn process(&mut self, samples: &[i32]) {
+        let frames = (samples.len() / 2) as u64;
+        let block_us = (frames * 1_000_000 / self.sample_rate_hz as u64) as u32;
+        let attack = keep_q16(block_us, self.ballistics.attack_ms);
+        let release = keep_q16(block_us, self.ballistics.release_ms);
+        let hold_us = self.ballistics.hold_ms.saturating_mul(1000);
+        for (index, state) in self.channels.iter_mut().enumerate() {
+            let mut peak = 0;
+            let mut sum = 0u128;
+            for &sample in samples.iter().skip(index).step_by(2) {
+                peak = peak.max(sample.unsigned_abs());
+                sum += (sample as i64 * sample as i64) as u128;
+            }
+            let rms = math::isqrt((sum / frames.max(1) as u128) as u64);
+            state.peak = smooth(state.peak, peak, attack, release);
+            state.rms = smooth(state.rms, rms, attack, release);
+            state.hold_us = state.hold_us.saturating_sub(block_us);
+            if peak >= state.hold || state.hold_us == 0 {
+                state.hold = peak.max(state.peak);
+                state.hold_us = hold_us;
+            }
+        }
+    }
+
+    pub fn left(&self) -> Level {
+        self.level(0)
+    }
+
+    pub fn right(&self) -> Level {
+        self.level(1)
+    }
+
+    fn level(&self, index: usize) -> Level {
+        let state = &self.channels[index];
+        Level {
+            peak: Centibels::from_ratio(state.peak, self.full_scale),
+            rms: Centibels::from_ratio(state.rms, self.full_scale),
+            peak_hold: Centibels::from_ratio(state.hold, self.full_scale),
+        }
+    }
+}
+
+/// Part of the previous level kept after `block_us`, in Q16, ie `exp(-t / tau)`.
+fn keep_q16(block_us: u32, tau_ms: u32) -> u32 {
+    if tau_ms == 0 {
+        return 0;
+    }
+    // exp(-t / tau) = 2^(-t / (tau * ln2))
+    let den = tau_ms as i64 * 1000 * LN2_Q16;
+    let x = -((block_us as i64) << 32) / den;
+    math::exp2_q16(x.max(i32::MIN as i64) as i32)
+}
+
+fn smooth(previous: u32, level: u32, attack: u32, release: u32) -> u32 {
+    let keep = if level > previous { attack } else { release } as i64;
+    let delta = previous as i64 - level as i64;
+    (level as i64 + ((delta * keep) >> 16)) as u32
+}
+
+/// Single frequency detector.
+///
+/// Best accuracy is reached when the block holds a whole number of periods of the frequency.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct Goertzel {
+    /// 2*cos(w) in Q30.
+    coeff: i64,
+    s1: i64,
+    s2: i64,
+    count: u32,
+}
+
+impl Goertzel {
+    pub fn new(hz: u32, sample_rate_hz: u32) -> Self {
+        let increment = ((hz as u64) << 32)
+            .checked_div(sample_rate_hz as u64)
+            .unwrap_or(0) as u32;
+        Self {
+            // cos in Q31 is 2*cos in Q30
+            coeff: math::sin_q31(increment.wrapping_add(1 << 30)) as i64,
+            s1: 0,
+            s2: 0,
+            count: 0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.s1 = 0;
+        self.s2 = 0;
+        self.count = 0;
+    }
+
+    pub fn update(&mut self, sample: i32) {
+        let s0 = sample as i64 + ((self.coeff as i128 * self.s1 as i128) >> 30) as i64 - self.s2;
+        self.s2 = self.s1;
+        self.s1 = s0;
+        self.count += 1;
+    }
+
+    pub fn process(&mut self, samples: &[i32]) {
+        for &sample in samples {
+            self.update(sample);
+        }
+    }
+
+    /// Number of samples processed since the last reset.
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Peak amplitude of the frequency over the processed samples.
+    pub fn amplitude(&self) -> u32 {
+        if self.count == 0 {
+            return 0;
+        }
+        let (s1, s2) = (self.s1 as i128, self.s2 as i128);
+        let power = s1 * s1 + s2 * s2 - ((self.coeff as i128 * s1) >> 30) * s2;
+        let magnitude = isqrt_u128(power.max(0) as u128);
+        (2 * magnitude / self.count as u128).min(u32::MAX as u128) as u32
+    }
+}
+
+fn isqrt_u128(x: u128) -> u128 {
+    let shift = (128 - x.leading_zeros()).saturating_sub(64).div_ceil(2);
+    (math::isqrt((x >> (2 * shift)) as u64) as u128) << shift
+}
+
+/// Result of [`analyze_tone`].
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct ToneAnalysis {
+    /// Peak amplitude of the fundamental.
+    pub amplitude: u32,
+    /// Peak level of the fundamental in dBFS.
+    pub level: Centibels,
+    /// Everything but the fundamental and DC, relative to the fundamental.
+    pub thd_n: Centibels,
+    /// Mean of the samples.
+    pub dc: i32,
+}
+
+/// Measure a test tone of frequency `hz` in a block of mono samples.
+///
+/// The fundamental is fitted by correlation and subtracted, THD+N being what is left. The block
+/// should hold a whole number of periods.
+pub fn analyze_tone(samples: &[i32], hz: u32, sample_rate_hz: u32, iwl: IwlV) -> ToneAnalysis {
+    let len = samples.len().max(1) as i64;
+    let dc = samples.iter().map(|&s| s as i64).sum::<i64>() / len;
+    let increment = ((hz as u64) << 32)
+        .checked_div(sample_rate_hz as u64)
+        .unwrap_or(0) as u32;
+    let quadrature = |index: usize| {
+        let phase = increment.wrapping_mul(index as u32);
+        (
+            math::sin_q31(phase.wrapping_add(1 << 30)) as i128,
+            math::sin_q31(phase) as i128,
+        )
+    };
+    let (mut in_phase, mut in_quadrature) = (0i128, 0i128);
+    for (index, &sample) in samples.iter().enumerate() {
+        let (cos, sin) = quadrature(index);
+        in_phase += (sample as i64 - dc) as i128 * cos;
+        in_quadrature += (sample as i64 - dc) as i128 * sin;
+    }
+    // Q31 coefficients of the fitted cosine and sine
+    let a = in_phase * 2 / len as i128;
+    let b = in_quadrature * 2 / len as i128;
+    let mut residual = 0u128;
+    for (index, &sample) in samples.iter().enumerate() {
+        let (cos, sin) = quadrature(index);
+        let fit = (a * cos + b * sin) >> 62;
+        let error = (sample as i64 - dc) as i128 - fit;
+        residual += (error * error) as u128;
+    }
+    let amplitude = isqrt_u128(((a * a + b * b) >> 62) as u128) as u32;
+    // mean square of a sine is A^2 / 2
+    let fundamental = (amplitude as u128 * amplitude as u128) / 2;
+    ToneAnalysis {
+        amplitude,
+        level: Centibels::from_ratio(amplitude, iwl.full_scale()),
+        thd_n: Centibels::from_ratio(
+            isqrt_u128(residual / len as u128) as u32,
+            isqrt_u128(fundamental) as u32,
+        ),
+        dc: dc as i32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::{Generator, Waveform};
+
+    #[test]
+    fn ballistics_test() {
+        let mut meter = LevelMeter::new(Ballistics::PEAK, 48_000, IwlV::Iwl16Bits);
+        let mut generator = Generator::new(
+            Waveform::Square { hz: 1000 },
+            Centibels(-60),
+            48_000,
+            IwlV::Iwl16Bits,
+        );
+        let mut block = [0; 960];
+        generator.fill(&mut block);
+        meter.process(&block);
+        assert_eq!(meter.left().peak.0, -60);
+        assert_eq!(meter.right().rms.0, -60);
+        // release of 650ms: about 10% lower after 70ms
+        let silence = [0; 960];
+        for _ in 0..7 {
+            meter.process(&silence);
+        }
+        let level = meter.left();
+        assert!((level.peak.0 + 69).abs() <= 2);
+        assert_eq!(level.peak_hold.0, -60);
+        for _ in 0..100 {
+            meter.process(&silence);
+        }
+        assert!(meter.left().peak_hold.0 < -70);
+        let mut meter = LevelMeter::new(Ballistics::INSTANT, 48_000, IwlV::Iwl16Bits);
+        meter.process(&block);
+        meter.process(&silence);
+        assert_eq!(meter.left().rms, Centibels(i16::MIN));
+    }
+
+    #[test]
+    fn goertzel_test() {
+        let mut generator = Generator::new(
+            Waveform::Sine { hz: 1000 },
+            Centibels(-60),
+            48_000,
+            IwlV::Iwl24Bits,
+        );
+        let mut samples = [0; 480];
+        generator.fill_mono(&mut samples);
+        let mut goertzel = Goertzel::new(1000, 48_000);
+        goertzel.process(&samples);
+        let expected = generator.amplitude();
+        assert!(goertzel.amplitude().abs_diff(expected) < expected / 1000);
+        let mut goertzel = Goertzel::new(3000, 48_000);
+        goertzel.process(&samples);
+        assert!(goertzel.amplitude() < expected / 1000);
+    }
+
+    #[test]
+    fn thd_n_test() {
+        let mut generator = Generator::new(
+            Waveform::Sine { hz: 1000 },
+            Centibels(-10),
+            48_000,
+            IwlV::Iwl16Bits,
+        );
+        let mut samples = [0; 960];
+        generator.fill_mono(&mut samples);
+        let clean = analyze_tone(&samples, 1000, 48_000, IwlV::Iwl16Bits);
+        assert!((clean.level.0 + 10).abs() <= 1);
+        assert!(clean.thd_n.0 < -800);
+        // clip the tone at half amplitude
+        let limit = generator.amplitude() as i32 / 2;
+        for sample in samples.iter_mut() {
+            *sample = (*sample).clamp(-limit, limit) + 100;
+        }
+        let clipped = analyze_tone(&samples, 1000, 48_000, IwlV::Iwl16Bits);
+        assert!((clipped.dc - 100).abs() <= 1);
+        assert!(clipped.thd_n.0 > -250 && clipped.thd_n.0 < -100);
+    }
+}
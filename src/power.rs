@@ -0,0 +1,160 @@
+//! Power profiles and sequencing timings.
+//!
+//! The datasheet recommend a specific order to power up and down the codec to minimise pops.
+//! [`Wm8731::power_up`](crate::Wm8731::power_up) and
+//! [`Wm8731::power_down`](crate::Wm8731::power_down) implement those sequences; this module
+//! describe which blocks to power and how long to wait between steps.
+//!
+//! [`UseCase`] gives the minimal profile and the matching analogue routing for common uses.
+use crate::InselV;
+
+/// Time to wait for VMID to settle after powering the codec, in milliseconds.
+///
+/// VMID charging time depends on the decoupling capacitor connected to the VMID pin. This
+/// value suits the recommended 10µF capacitor.
+pub const VMID_SETTLE_MS: u16 = 500;
+
+/// Time to wait for the DAC soft mute to complete, in milliseconds.
+pub const SOFT_MUTE_MS: u16 = 50;
+
+/// Time to wait for outputs to discharge after powering them down, in milliseconds.
+pub const OUTPUT_DISCHARGE_MS: u16 = 100;
+
+/// Blocks to power. `true` means powered.
+///
+/// Oscillator and CLKOUT are not part of a profile, they are handled by
+/// [`ClockConfig`](crate::clock::ClockConfig).
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Default)]
+pub struct PowerProfile {
+    pub line_in: bool,
+    pub mic: bool,
+    pub adc: bool,
+    pub dac: bool,
+    pub outputs: bool,
+}
+
+impl PowerProfile {
+    /// Every block powered.
+    pub const ALL: PowerProfile = PowerProfile {
+        line_in: true,
+        mic: true,
+        adc: true,
+        dac: true,
+        outputs: true,
+    };
+
+    /// Every block powered down.
+    pub const NONE: PowerProfile = PowerProfile {
+        line_in: false,
+        mic: false,
+        adc: false,
+        dac: false,
+        outputs: false,
+    };
+}
+
+/// Analogue Audio Path routing fields.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct PathRouting {
+    pub dacsel: bool,
+    pub bypass: bool,
+    pub insel: InselV,
+    pub sidetone: bool,
+}
+
+impl Default for PathRouting {
+    /// Same as the register reset value.
+    fn default() -> Self {
+        Self {
+            dacsel: false,
+            bypass: true,
+            insel: InselV::Line,
+            sidetone: false,
+        }
+    }
+}
+
+/// Common uses of the codec.
+///
+/// A use case sets power and routing only, mutes are left as they are. From reset, line inputs
+/// (`LINMUTE`, `RINMUTE`), microphone (`MUTEMIC`) and DAC (`DACMU`) are muted, so the source of a
+/// use case must be unmuted after switching to it, for example with
+/// [`Wm8731::set_both_inmute`](crate::Wm8731::set_both_inmute) for [`UseCase::RecordLine`] or
+/// [`Wm8731::set_mutemic`](crate::Wm8731::set_mutemic) for [`UseCase::RecordMic`]. Keeping them
+/// muted let the caller unmute once the signal chain settled.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum UseCase {
+    /// DAC to outputs.
+    Playback,
+    /// Line in to ADC.
+    RecordLine,
+    /// Microphone to ADC.
+    RecordMic,
+    /// Line in directly to outputs, without conversion.
+    Bypass,
+    /// Line in to ADC and DAC to outputs.
+    Duplex,
+    /// Nothing powered except the reference and clocks.
+    Standby,
+}
+
+impl UseCase {
+    /// Minimal set of blocks to power.
+    pub const fn power_profile(self) -> PowerProfile {
+        let none = PowerProfile::NONE;
+        match self {
+            UseCase::Playback => PowerProfile {
+                dac: true,
+                outputs: true,
+                ..none
+            },
+            UseCase::RecordLine => PowerProfile {
+                line_in: true,
+                adc: true,
+                ..none
+            },
+            UseCase::RecordMic => PowerProfile {
+                mic: true,
+                adc: true,
+                ..none
+            },
+            UseCase::Bypass => PowerProfile {
+                line_in: true,
+                outputs: true,
+                ..none
+            },
+            UseCase::Duplex => PowerProfile {
+                line_in: true,
+                adc: true,
+                dac: true,
+                outputs: true,
+                ..none
+            },
+            UseCase::Standby => none,
+        }
+    }
+
+    /// Matching analogue routing.
+    pub const fn routing(self) -> PathRouting {
+        let (dacsel, bypass, insel) = match self {
+            UseCase::Playback => (true, false, InselV::Line),
+            UseCase::RecordLine => (false, false, InselV::Line),
+            UseCase::RecordMic => (false, false, InselV::Mic),
+            UseCase::Bypass => (false, true, InselV::Line),
+            UseCase::Duplex => (true, false, InselV::Line),
+            UseCase::Standby => (false, false, InselV::Line),
+        };
+        PathRouting {
+            dacsel,
+            bypass,
+            insel,
+            sidetone: false,
+        }
+    }
+}
+
+impl From<UseCase> for PowerProfile {
+    fn from(use_case: UseCase) -> Self {
+        use_case.power_profile()
+    }
+}
@@ -108,7 +108,7 @@ impl AnalogueAudioPath {
     /// Set attenuation applyed to the sidetone path.
     pub fn set_sideatt(&mut self, value: SideAttdB) -> &mut Self {
         let pos = 6;
-        self.data = self.data & !(0b11 << pos) | (value.into_raw() as u8);
+        self.data = self.data & !(0b11 << pos) | value.into_raw() << pos;
         self
     }
 }
@@ -119,3 +119,27 @@ pub enum InselV {
     Line = 0,
     Mic = 1,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn sideatt_test() {
+        let values = [
+            SideAttdB::N6DB,
+            SideAttdB::N9DB,
+            SideAttdB::N12DB,
+            SideAttdB::N15DB,
+        ];
+        for (raw, value) in values.into_iter().enumerate() {
+            let mut path = AnalogueAudioPath::new();
+            path.set_sideatt(value);
+            assert_eq!(path.sideatt(), value);
+            // other fields keep their reset value
+            assert_eq!(
+                path.to_frame().data,
+                0b100 << 9 | (raw as u16) << 6 | 0b1010
+            );
+        }
+    }
+}
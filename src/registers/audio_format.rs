@@ -0,0 +1,210 @@
+//! Validated Digital Audio Interface Format.
+use super::digital_audio_interface::{FormatV, IwlV};
+use core::fmt;
+
+///Error returned when trying to build an invalid `AudioFormat`.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum AudioFormatError {
+    ///Right justified mode doesn't support 32 bits words.
+    RightJustified32Bits,
+    ///In DSP mode, `LRP` select the DSP mode, so left/right clock can't be inverted.
+    DspLrclkInverted,
+}
+
+impl fmt::Display for AudioFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AudioFormatError::RightJustified32Bits => {
+                f.write_str("32 bits words are not supported in right justified mode")
+            }
+            AudioFormatError::DspLrclkInverted => {
+                f.write_str("left/right clock can't be inverted in DSP mode")
+            }
+        }
+    }
+}
+
+/// DSP mode variant. In DSP mode, this replace the `LRP` field meaning.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum DspMode {
+    /// MSB is available on 2nd BCLK rising edge after DACLRC/ADCLRC rising edge (`LRP` = 1).
+    A,
+    /// MSB is available on 1st BCLK rising edge after DACLRC/ADCLRC rising edge (`LRP` = 0).
+    B,
+}
+
+/// Audio data standard, with the `FORMAT` field and the DSP mode.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum AudioStandard {
+    RightJustified,
+    LeftJustified,
+    I2s,
+    Dsp { mode: DspMode },
+}
+
+impl AudioStandard {
+    /// Return the corresponding `FORMAT` field value.
+    pub const fn format(self) -> FormatV {
+        match self {
+            AudioStandard::RightJustified => FormatV::RigthJustified,
+            AudioStandard::LeftJustified => FormatV::LeftJustified,
+            AudioStandard::I2s => FormatV::I2s,
+            AudioStandard::Dsp { .. } => FormatV::Dsp,
+        }
+    }
+}
+
+/// Validated digital audio interface format. Bundle `FORMAT`, `IWL`, `LRP` and `BCLKINV` fields.
+///
+/// Those fields are closely related, for example `LRP` doesn't have the same meaning in DSP mode
+/// and other modes, and some combinations are forbidden by the datasheet. This type give them
+/// an explicit meaning and prevent forbidden combinations.
+///
+/// Some presets are provided as associated constants. For presets named `<STD>_<IWL>_IN_<SLOT>`,
+/// the codec use `<IWL>` bits words, and the other side of the link is expected to use `<SLOT>`
+/// bits channel frames.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct AudioFormat {
+    standard: AudioStandard,
+    iwl: IwlV,
+    lrclk_inverted: bool,
+    bclk_inverted: bool,
+}
+
+impl AudioFormat {
+    const fn new_unchecked(standard: AudioStandard, iwl: IwlV) -> Self {
+        Self {
+            standard,
+            iwl,
+            lrclk_inverted: false,
+            bclk_inverted: false,
+        }
+    }
+
+    /// Build a format from a standard and an input word length. Left/right clock and bit clock are
+    /// not inverted.
+    pub fn new(standard: AudioStandard, iwl: IwlV) -> Result<Self, AudioFormatError> {
+        if standard == AudioStandard::RightJustified && iwl == IwlV::Iwl32Bits {
+            return Err(AudioFormatError::RightJustified32Bits);
+        }
+        Ok(Self::new_unchecked(standard, iwl))
+    }
+
+    /// Invert left/right clock polarity, i.e. right channel is sent when DACLRC is high. Not
+    /// available in DSP mode.
+    pub fn with_lrclk_inverted(self, value: bool) -> Result<Self, AudioFormatError> {
+        if value && matches!(self.standard, AudioStandard::Dsp { .. }) {
+            return Err(AudioFormatError::DspLrclkInverted);
+        }
+        Ok(Self {
+            lrclk_inverted: value,
+            ..self
+        })
+    }
+
+    /// Invert bit clock polarity.
+    pub const fn with_bclk_inverted(self, value: bool) -> Self {
+        Self {
+            bclk_inverted: value,
+            ..self
+        }
+    }
+
+    /// Build a format from raw field values. Fail if the combination is forbidden.
+    pub fn from_fields(
+        format: FormatV,
+        iwl: IwlV,
+        lrp: bool,
+        bclkinv: bool,
+    ) -> Result<Self, AudioFormatError> {
+        let (standard, lrclk_inverted) = match format {
+            FormatV::RigthJustified => (AudioStandard::RightJustified, lrp),
+            FormatV::LeftJustified => (AudioStandard::LeftJustified, lrp),
+            FormatV::I2s => (AudioStandard::I2s, lrp),
+            FormatV::Dsp => {
+                let mode = if lrp { DspMode::A } else { DspMode::B };
+                (AudioStandard::Dsp { mode }, false)
+            }
+        };
+        Ok(Self::new(standard, iwl)?
+            .with_lrclk_inverted(lrclk_inverted)?
+            .with_bclk_inverted(bclkinv))
+    }
+
+    pub const fn standard(&self) -> AudioStandard {
+        self.standard
+    }
+    pub const fn iwl(&self) -> IwlV {
+        self.iwl
+    }
+    pub const fn lrclk_inverted(&self) -> bool {
+        self.lrclk_inverted
+    }
+    pub const fn bclk_inverted(&self) -> bool {
+        self.bclk_inverted
+    }
+
+    /// Return the `FORMAT` field value.
+    pub const fn format(&self) -> FormatV {
+        self.standard.format()
+    }
+
+    /// Return the `LRP` field value.
+    pub const fn lrp(&self) -> bool {
+        match self.standard {
+            AudioStandard::Dsp { mode: DspMode::A } => true,
+            AudioStandard::Dsp { mode: DspMode::B } => false,
+            _ => self.lrclk_inverted,
+        }
+    }
+
+    /// Return the `BCLKINV` field value.
+    pub const fn bclkinv(&self) -> bool {
+        self.bclk_inverted
+    }
+}
+
+/// Presets.
+#[rustfmt::skip]
+impl AudioFormat {
+    pub const I2S_16:                    AudioFormat = AudioFormat::new_unchecked(AudioStandard::I2s, IwlV::Iwl16Bits);
+    pub const I2S_24_IN_32:              AudioFormat = AudioFormat::new_unchecked(AudioStandard::I2s, IwlV::Iwl24Bits);
+    pub const I2S_32:                    AudioFormat = AudioFormat::new_unchecked(AudioStandard::I2s, IwlV::Iwl32Bits);
+    pub const LEFT_JUSTIFIED_16:         AudioFormat = AudioFormat::new_unchecked(AudioStandard::LeftJustified, IwlV::Iwl16Bits);
+    pub const LEFT_JUSTIFIED_24_IN_32:   AudioFormat = AudioFormat::new_unchecked(AudioStandard::LeftJustified, IwlV::Iwl24Bits);
+    pub const RIGHT_JUSTIFIED_16:        AudioFormat = AudioFormat::new_unchecked(AudioStandard::RightJustified, IwlV::Iwl16Bits);
+    pub const RIGHT_JUSTIFIED_24_IN_32:  AudioFormat = AudioFormat::new_unchecked(AudioStandard::RightJustified, IwlV::Iwl24Bits);
+    pub const DSP_A_16:                  AudioFormat = AudioFormat::new_unchecked(AudioStandard::Dsp { mode: DspMode::A }, IwlV::Iwl16Bits);
+    pub const DSP_B_16:                  AudioFormat = AudioFormat::new_unchecked(AudioStandard::Dsp { mode: DspMode::B }, IwlV::Iwl16Bits);
+}
+
+impl Default for AudioFormat {
+    /// Same as the register reset value.
+    fn default() -> Self {
+        AudioFormat::I2S_24_IN_32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn forbidden_combination_test() {
+        let res = AudioFormat::new(AudioStandard::RightJustified, IwlV::Iwl32Bits);
+        assert_eq!(res, Err(AudioFormatError::RightJustified32Bits));
+        let res = AudioFormat::DSP_A_16.with_lrclk_inverted(true);
+        assert_eq!(res, Err(AudioFormatError::DspLrclkInverted));
+        let res = AudioFormat::from_fields(FormatV::RigthJustified, IwlV::Iwl32Bits, false, false);
+        assert_eq!(res, Err(AudioFormatError::RightJustified32Bits));
+    }
+    #[test]
+    fn lrp_meaning_test() {
+        assert!(AudioFormat::DSP_A_16.lrp());
+        assert!(!AudioFormat::DSP_B_16.lrp());
+        let fmt = AudioFormat::I2S_16.with_lrclk_inverted(true).unwrap();
+        assert!(fmt.lrp());
+        let fmt = AudioFormat::from_fields(FormatV::Dsp, IwlV::Iwl16Bits, true, false).unwrap();
+        assert_eq!(fmt, AudioFormat::DSP_A_16);
+        assert!(!fmt.lrclk_inverted());
+    }
+}
@@ -2,6 +2,8 @@
 #![allow(clippy::new_without_default)]
 use crate::interface::Frame;
 
+use super::audio_format::{AudioFormat, AudioFormatError};
+
 /// Digital Audio Interface Format register.
 #[derive(Debug, Eq, PartialEq, Clone, Copy)]
 pub struct DigitalAudioInterface {
@@ -65,6 +67,11 @@ impl DigitalAudioInterface {
         self.data & (1 << pos) == 1 << pos
     }
 
+    /// Get the whole audio format. Fail if fields contain a forbidden combination.
+    pub fn audio_format(&self) -> Result<AudioFormat, AudioFormatError> {
+        AudioFormat::from_fields(self.format(), self.iwl(), self.lrp(), self.bclkinv())
+    }
+
     pub fn set_format(&mut self, value: FormatV) -> &mut Self {
         let pos = 0;
         self.data = self.data & !(0b11 << pos) | (value as u8) << pos;
@@ -95,6 +102,13 @@ impl DigitalAudioInterface {
         self.data = self.data & !(1 << pos) | (value as u8) << pos;
         self
     }
+    /// Set `FORMAT`, `IWL`, `LRP` and `BCLKINV` fields at once.
+    pub fn set_audio_format(&mut self, value: AudioFormat) -> &mut Self {
+        self.set_format(value.format())
+            .set_iwl(value.iwl())
+            .set_lrp(value.lrp())
+            .set_bclkinv(value.bclkinv())
+    }
 }
 
 /// Audio data format selection.
@@ -115,6 +129,23 @@ pub enum IwlV {
     Iwl16Bits = 0b00,
 }
 
+impl IwlV {
+    /// Number of bits of a sample.
+    pub const fn bits(self) -> u8 {
+        match self {
+            IwlV::Iwl16Bits => 16,
+            IwlV::Iwl20Bits => 20,
+            IwlV::Iwl24Bits => 24,
+            IwlV::Iwl32Bits => 32,
+        }
+    }
+
+    /// Magnitude of a full scale sample, ie `2^(bits - 1)`.
+    pub const fn full_scale(self) -> u32 {
+        1 << (self.bits() - 1)
+    }
+}
+
 /// Master or slave mode selection.
 #[derive(Debug, Eq, PartialEq, Clone, Copy)]
 pub enum MsV {
@@ -73,12 +73,39 @@ impl DigitalAudioPath {
         self.data = self.data & !(1 << pos) | (value as u8) << pos;
         self
     }
+    pub fn adc_high_pass(&self) -> AdcHighPass {
+        match (self.adchpd(), self.hpor()) {
+            (false, _) => AdcHighPass::Enabled,
+            (true, false) => AdcHighPass::Disabled,
+            (true, true) => AdcHighPass::FrozenOffset,
+        }
+    }
+    /// `HPOR` is left unchanged when enabling the filter, it has no effect while the filter runs.
+    pub fn set_adc_high_pass(&mut self, value: AdcHighPass) -> &mut Self {
+        match value {
+            AdcHighPass::Enabled => self.set_adchpd(false),
+            AdcHighPass::Disabled => self.set_adchpd(true).set_hpor(false),
+            AdcHighPass::FrozenOffset => self.set_adchpd(true).set_hpor(true),
+        }
+    }
 }
 
 /// De-emphasis filter selection.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
 pub enum DeempV {
     Disable = 0b00,
     F32k = 0b01,
     F44k1 = 0b10,
     F48k = 0b11,
 }
+
+/// ADC high pass filter mode, combining `ADCHPD` and `HPOR`.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum AdcHighPass {
+    /// Filter running, DC is removed continuously.
+    Enabled,
+    /// Filter disabled, DC goes through.
+    Disabled,
+    /// Filter disabled, the last offset measured by the filter is subtracted.
+    FrozenOffset,
+}
@@ -1,13 +1,11 @@
+use crate::db::{self, Centibels, DbError, Rounding};
+use crate::gain::{Gain, ScaleError};
 use core::fmt;
+use core::ops::{Add, Sub};
+use core::str::FromStr;
 
 ///Error returned when trying to scale a value into HpVoldB.
-#[derive(Debug, Eq, PartialEq, Copy, Clone)]
-pub enum HpVoldBScaleError {
-    ///The given range is null.
-    NullRange,
-    ///The given value is outside the given range.
-    OutOfRange,
-}
+pub type HpVoldBScaleError = ScaleError;
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 /// Headphone volume in dB
@@ -53,35 +51,17 @@ impl HpVoldB {
         high_limit: i16,
         input: i16,
     ) -> Result<Self, HpVoldBScaleError> {
-        if low_limit == high_limit {
-            return Err(HpVoldBScaleError::NullRange);
-        } else if (low_limit < high_limit && (input < low_limit || input > high_limit))
-            || (low_limit > high_limit && (input > low_limit || input < high_limit))
-        {
-            return Err(HpVoldBScaleError::OutOfRange);
-        }
-
-        const MIN: u8 = HpVoldB::MIN.inner;
-        const MAX: u8 = HpVoldB::MAX.inner;
-        let r1 = high_limit - low_limit;
-        let r2 = MAX - MIN;
-        let res = (((input - low_limit) * r2 as i16) + r1 / 2) / (r1) + MIN as i16;
-        let res = unsafe { HpVoldB::from_raw_unchecked(res as _) };
-        Ok(res)
+        <Self as Gain>::from_scaled(low_limit, high_limit, input)
     }
 
     ///Increase the value by one step. Saturated to `HpVoldB::MAX`.
     pub fn increase(&mut self) {
-        if self.inner < HpVoldB::MAX.inner {
-            self.inner += 1;
-        }
+        Gain::increase(self)
     }
 
     ///Decrease the value by one step. Saturated to `HpVoldB::MIN`.
     pub fn decrease(&mut self) {
-        if self.inner > HpVoldB::MIN.inner {
-            self.inner -= 1;
-        }
+        Gain::decrease(self)
     }
     ///Represent a mute value
     pub const MUTE: HpVoldB = unsafe { HpVoldB::from_raw_unchecked(0b0101111) };
@@ -250,6 +230,102 @@ impl HpVoldB {
     ///Represent the greatest value
     pub const MAX: HpVoldB = HpVoldB::P6DB;
 }
+/// dB conversions.
+impl HpVoldB {
+    /// Gain of the lowest value that isn't mute.
+    const MIN_CB: Centibels = Centibels(-730);
+
+    /// Return the gain in centibels, or `None` for mute.
+    pub const fn to_centibels(self) -> Option<Centibels> {
+        if self.inner == HpVoldB::MUTE.inner {
+            None
+        } else {
+            Some(Centibels(
+                (self.inner as i16 - HpVoldB::Z0DB.inner as i16) * 10,
+            ))
+        }
+    }
+
+    /// Return the step matching a gain. Saturate to the range, mute is never returned.
+    pub fn from_centibels(cb: Centibels, rounding: Rounding) -> Self {
+        let max_index = HpVoldB::MAX.inner - HpVoldB::N73DB.inner;
+        let index = db::step_index(cb, Self::MIN_CB, 10, max_index, rounding);
+        Self {
+            inner: HpVoldB::N73DB.inner + index,
+        }
+    }
+
+    /// Return the step exactly matching a gain.
+    pub fn try_from_centibels(cb: Centibels) -> Result<Self, DbError> {
+        let max_index = HpVoldB::MAX.inner - HpVoldB::N73DB.inner;
+        let index = db::exact_step_index(cb, Self::MIN_CB, 10, max_index)?;
+        Ok(Self {
+            inner: HpVoldB::N73DB.inner + index,
+        })
+    }
+}
+
+impl Gain for HpVoldB {
+    const MIN: Self = HpVoldB::MIN;
+    const MAX: Self = HpVoldB::MAX;
+    const STEP: Centibels = Centibels(10);
+
+    unsafe fn from_raw_unchecked(raw: u8) -> Self {
+        Self { inner: raw }
+    }
+
+    fn into_raw(self) -> u8 {
+        self.inner
+    }
+
+    fn to_centibels(self) -> Option<Centibels> {
+        self.to_centibels()
+    }
+
+    fn from_centibels(cb: Centibels, rounding: Rounding) -> Self {
+        HpVoldB::from_centibels(cb, rounding)
+    }
+}
+
+impl Add<Centibels> for HpVoldB {
+    type Output = HpVoldB;
+    /// Add a gain, saturating to the range. Mute stays mute.
+    fn add(self, rhs: Centibels) -> HpVoldB {
+        match self.to_centibels() {
+            Some(cb) => HpVoldB::from_centibels(cb + rhs, Rounding::Nearest),
+            None => HpVoldB::MUTE,
+        }
+    }
+}
+
+impl Sub<Centibels> for HpVoldB {
+    type Output = HpVoldB;
+    /// Subtract a gain, saturating to the range. Mute stays mute.
+    fn sub(self, rhs: Centibels) -> HpVoldB {
+        self + -rhs
+    }
+}
+
+impl TryFrom<i16> for HpVoldB {
+    type Error = DbError;
+    /// Convert an integer number of dB. Fail if it doesn't match a step exactly.
+    fn try_from(db: i16) -> Result<Self, DbError> {
+        HpVoldB::try_from_centibels(Centibels::from_db(db))
+    }
+}
+
+impl FromStr for HpVoldB {
+    type Err = DbError;
+    /// Parse strings like `-12dB`, `+1.5dB` or `-3` or `mute`. The value must match a step
+    /// exactly.
+    fn from_str(s: &str) -> Result<Self, DbError> {
+        match db::parse(s)? {
+            Some(cb) => HpVoldB::try_from_centibels(cb),
+            None => Ok(HpVoldB::MUTE),
+        }
+    }
+}
+
 impl fmt::Display for HpVoldB {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let fmt = match self.inner {
@@ -387,4 +463,21 @@ mod tests {
             HpVoldB::MIN
         );
     }
+    #[test]
+    fn db_test() {
+        assert_eq!(HpVoldB::N12DB.to_centibels(), Some(Centibels(-120)));
+        assert_eq!(HpVoldB::MUTE.to_centibels(), None);
+        let vol = HpVoldB::from_centibels(Centibels(-125), Rounding::Down);
+        assert_eq!(vol, HpVoldB::N13DB);
+        let vol = HpVoldB::from_centibels(Centibels(-2000), Rounding::Nearest);
+        assert_eq!(vol, HpVoldB::N73DB);
+        assert_eq!(HpVoldB::P5DB + Centibels(30), HpVoldB::P6DB);
+        assert_eq!(HpVoldB::N12DB - Centibels(30), HpVoldB::N15DB);
+        assert_eq!(HpVoldB::MUTE + Centibels(30), HpVoldB::MUTE);
+        assert_eq!(HpVoldB::try_from(-12), Ok(HpVoldB::N12DB));
+        assert_eq!(HpVoldB::try_from(7), Err(DbError::OutOfRange));
+        assert_eq!("-12dB".parse::<HpVoldB>(), Ok(HpVoldB::N12DB));
+        assert_eq!("mute".parse::<HpVoldB>(), Ok(HpVoldB::MUTE));
+        assert_eq!("-12.5dB".parse::<HpVoldB>(), Err(DbError::NotRepresentable));
+    }
 }
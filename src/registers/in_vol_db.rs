@@ -1,12 +1,10 @@
+use crate::db::{self, Centibels, DbError, Rounding};
+use crate::gain::{Gain, ScaleError};
 use core::fmt;
+use core::ops::{Add, Sub};
+use core::str::FromStr;
 ///Error returned when trying to scale a value into InVoldB.
-#[derive(Debug, Eq, PartialEq, Copy, Clone)]
-pub enum InVoldBScaleError {
-    ///The given range is null.
-    NullRange,
-    ///The given value is outside the given range.
-    OutOfRange,
-}
+pub type InVoldBScaleError = ScaleError;
 
 /// Abstraction to represent input volume in dB.
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
@@ -49,35 +47,17 @@ impl InVoldB {
         high_limit: i16,
         input: i16,
     ) -> Result<Self, InVoldBScaleError> {
-        if low_limit == high_limit {
-            return Err(InVoldBScaleError::NullRange);
-        } else if (low_limit < high_limit && (input < low_limit || input > high_limit))
-            || (low_limit > high_limit && (input > low_limit || input < high_limit))
-        {
-            return Err(InVoldBScaleError::OutOfRange);
-        }
-
-        const MIN: u8 = InVoldB::MIN.inner;
-        const MAX: u8 = InVoldB::MAX.inner;
-        let r1 = high_limit - low_limit;
-        let r2 = MAX - MIN;
-        let res = (((input - low_limit) * r2 as i16) + r1 / 2) / (r1) + MIN as i16;
-        let res = unsafe { InVoldB::from_raw_unchecked(res as _) };
-        Ok(res)
+        <Self as Gain>::from_scaled(low_limit, high_limit, input)
     }
 
     ///Increase the value by one step. Saturated to `InVoldB::MAX`.
     pub fn increase(&mut self) {
-        if self.inner < InVoldB::MAX.inner {
-            self.inner += 1;
-        }
+        Gain::increase(self)
     }
 
     ///Decrease the value by one step. Saturated to `InVoldB::MIN`.
     pub fn decrease(&mut self) {
-        if self.inner > InVoldB::MIN.inner {
-            self.inner -= 1;
-        }
+        Gain::decrease(self)
     }
 
     ///Represent a value of -34.5dB
@@ -149,6 +129,93 @@ impl InVoldB {
     ///Represent the greatest value
     pub const MAX: InVoldB = InVoldB::P12DB;
 }
+/// dB conversions. Steps are 1.5dB, so centibels represent them exactly.
+impl InVoldB {
+    const MIN_CB: Centibels = Centibels(-345);
+    const STEP_CB: i16 = 15;
+
+    /// Return the gain in centibels.
+    pub const fn to_centibels(self) -> Centibels {
+        Centibels(Self::MIN_CB.0 + self.inner as i16 * Self::STEP_CB)
+    }
+
+    /// Return the step matching a gain. Saturate to the range.
+    pub fn from_centibels(cb: Centibels, rounding: Rounding) -> Self {
+        let index = db::step_index(
+            cb,
+            Self::MIN_CB,
+            Self::STEP_CB,
+            InVoldB::MAX.inner,
+            rounding,
+        );
+        Self { inner: index }
+    }
+
+    /// Return the step exactly matching a gain.
+    pub fn try_from_centibels(cb: Centibels) -> Result<Self, DbError> {
+        let index = db::exact_step_index(cb, Self::MIN_CB, Self::STEP_CB, InVoldB::MAX.inner)?;
+        Ok(Self { inner: index })
+    }
+}
+
+impl Gain for InVoldB {
+    const MIN: Self = InVoldB::MIN;
+    const MAX: Self = InVoldB::MAX;
+    const STEP: Centibels = Centibels(InVoldB::STEP_CB);
+
+    unsafe fn from_raw_unchecked(raw: u8) -> Self {
+        Self { inner: raw }
+    }
+
+    fn into_raw(self) -> u8 {
+        self.inner
+    }
+
+    fn to_centibels(self) -> Option<Centibels> {
+        Some(self.to_centibels())
+    }
+
+    fn from_centibels(cb: Centibels, rounding: Rounding) -> Self {
+        InVoldB::from_centibels(cb, rounding)
+    }
+}
+
+impl Add<Centibels> for InVoldB {
+    type Output = InVoldB;
+    /// Add a gain, saturating to the range.
+    fn add(self, rhs: Centibels) -> InVoldB {
+        InVoldB::from_centibels(self.to_centibels() + rhs, Rounding::Nearest)
+    }
+}
+
+impl Sub<Centibels> for InVoldB {
+    type Output = InVoldB;
+    /// Subtract a gain, saturating to the range.
+    fn sub(self, rhs: Centibels) -> InVoldB {
+        self + -rhs
+    }
+}
+
+impl TryFrom<i16> for InVoldB {
+    type Error = DbError;
+    /// Convert an integer number of dB. Fail if it doesn't match a step exactly.
+    fn try_from(db: i16) -> Result<Self, DbError> {
+        InVoldB::try_from_centibels(Centibels::from_db(db))
+    }
+}
+
+impl FromStr for InVoldB {
+    type Err = DbError;
+    /// Parse strings like `-12dB`, `+1.5dB` or `-3`. The value must match a step
+    /// exactly.
+    fn from_str(s: &str) -> Result<Self, DbError> {
+        match db::parse(s)? {
+            Some(cb) => InVoldB::try_from_centibels(cb),
+            None => Err(DbError::NotRepresentable),
+        }
+    }
+}
+
 impl fmt::Display for InVoldB {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let fmt = match self.inner {
@@ -233,4 +300,19 @@ mod tests {
             InVoldB::MIN
         );
     }
+    #[test]
+    fn db_test() {
+        assert_eq!(InVoldB::N10DB5.to_centibels(), Centibels(-105));
+        assert_eq!(InVoldB::MAX.to_centibels(), Centibels(120));
+        let vol = InVoldB::from_centibels(Centibels(-5), Rounding::Nearest);
+        assert_eq!(vol, InVoldB::Z0DB);
+        let vol = InVoldB::from_centibels(Centibels(-5), Rounding::Down);
+        assert_eq!(vol, InVoldB::N1DB5);
+        assert_eq!(InVoldB::P10DB5 + Centibels(30), InVoldB::P12DB);
+        assert_eq!(InVoldB::Z0DB - Centibels(30), InVoldB::N3DB);
+        assert_eq!(InVoldB::try_from(-12), Ok(InVoldB::N12DB));
+        assert_eq!(InVoldB::try_from(-11), Err(DbError::NotRepresentable));
+        assert_eq!("+1.5dB".parse::<InVoldB>(), Ok(InVoldB::P1DB5));
+        assert_eq!("mute".parse::<InVoldB>(), Err(DbError::NotRepresentable));
+    }
 }
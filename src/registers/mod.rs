@@ -2,6 +2,7 @@
 
 pub mod active;
 pub mod analogue_audio_path;
+pub mod audio_format;
 pub mod digital_audio_interface;
 pub mod digital_audio_path;
 pub mod headphone_out;
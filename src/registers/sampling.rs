@@ -43,7 +43,7 @@ impl Sampling {
     /// Set Sampling Rate. Replace USB/NORMAL, BOSR and SR setters.
     pub fn set_sampling_rates(&mut self, value: SamplingRates) -> &mut Self {
         let pos = 0;
-        self.data = self.data & !(0b111111 << pos) | (value.into_raw() as u8) << pos;
+        self.data = self.data & !(0b111111 << pos) | value.into_raw() << pos;
         self
     }
     pub fn set_clkidiv2(&mut self, value: bool) -> &mut Self {
@@ -167,6 +167,103 @@ impl SamplingRates {
     pub const fn into_raw(self) -> u8 {
         self.inner
     }
+
+    /// Return `true` for configurations using USB mode, i.e. a 12MHz core clock.
+    pub const fn is_usb(self) -> bool {
+        self.inner & 0b1 == 0b1
+    }
+
+    /// Return `false` when DAC soft mute doesn't work correctly, i.e. when `SR` is `0b0111` or
+    /// `0b1111`.
+    pub const fn soft_mute_supported(self) -> bool {
+        (self.inner >> 2) & 0b0111 != 0b0111
+    }
+
+    /// Return the core clock frequency this configuration is presented with in the datasheet,
+    /// following the letter of associated constant names.
+    pub const fn nominal_core_clock_hz(self) -> u32 {
+        if self.is_usb() {
+            return 12_000_000;
+        }
+        match (self.inner & 0b10 == 0b10, self.inner & 0b100000 == 0b100000) {
+            (false, false) => 12_288_000,
+            (false, true) => 11_289_600,
+            (true, false) => 18_432_000,
+            (true, true) => 16_934_400,
+        }
+    }
+
+    /// Return how many time the core clock is divided to generate the ADC sampling frequency.
+    ///
+    /// Return 0 for an unspecified raw value.
+    pub const fn adc_divider(self) -> u16 {
+        self.dividers().0
+    }
+
+    /// Return how many time the core clock is divided to generate the DAC sampling frequency.
+    ///
+    /// Return 0 for an unspecified raw value.
+    pub const fn dac_divider(self) -> u16 {
+        self.dividers().1
+    }
+
+    /// Compute ADC sampling frequency from the core clock frequency, rounded to nearest Hz.
+    ///
+    /// Return 0 for an unspecified raw value.
+    pub const fn adc_rate(self, core_clock_hz: u32) -> u32 {
+        div_round(core_clock_hz, self.adc_divider())
+    }
+
+    /// Compute DAC sampling frequency from the core clock frequency, rounded to nearest Hz.
+    ///
+    /// Return 0 for an unspecified raw value.
+    pub const fn dac_rate(self, core_clock_hz: u32) -> u32 {
+        div_round(core_clock_hz, self.dac_divider())
+    }
+
+    #[rustfmt::skip]
+    const fn dividers(self) -> (u16, u16) {
+        match self.inner {
+            0b000000 | 0b100000 => (256, 256),
+            0b000100 => (256, 1536),
+            0b001000 => (1536, 256),
+            0b001100 => (1536, 1536),
+            0b011000 => (384, 384),
+            0b011100 | 0b111100 => (128, 128),
+            0b100100 => (256, 1408),
+            0b101000 => (1408, 256),
+            0b101100 => (1408, 1408),
+            0b000010 | 0b100010 => (384, 384),
+            0b000110 => (384, 2304),
+            0b001010 => (2304, 384),
+            0b001110 => (2304, 2304),
+            0b011010 => (576, 576),
+            0b011110 | 0b111110 => (192, 192),
+            0b100110 => (384, 2112),
+            0b101010 => (2112, 384),
+            0b101110 => (2112, 2112),
+            0b000001 => (250, 250),
+            0b100011 => (272, 272),
+            0b000101 => (250, 1500),
+            0b100111 => (272, 1496),
+            0b001001 => (1500, 250),
+            0b101011 => (1496, 272),
+            0b001101 => (1500, 1500),
+            0b101111 => (1496, 1496),
+            0b011011 => (375, 375),
+            0b011101 => (125, 125),
+            0b111111 => (136, 136),
+            _ => (0, 0),
+        }
+    }
+}
+
+const fn div_round(num: u32, div: u16) -> u32 {
+    if div == 0 {
+        0
+    } else {
+        (num + div as u32 / 2) / div as u32
+    }
 }
 
 /// Sampling rate configuration usually used with a 12.288MHz core clock.
@@ -226,3 +323,36 @@ impl SamplingRates {
     pub const ADC125_DAC125_U:    SamplingRates = unsafe { SamplingRates::from_raw_unchecked(0b011101) };
     pub const ADC136_DAC136_U:    SamplingRates = unsafe { SamplingRates::from_raw_unchecked(0b111111) };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn rate_test() {
+        let rates = SamplingRates::ADC256_DAC1536_A;
+        assert_eq!(rates.adc_rate(12_288_000), 48_000);
+        assert_eq!(rates.dac_rate(12_288_000), 8_000);
+        let rates = SamplingRates::ADC1408_DAC1408_B;
+        assert_eq!(rates.adc_rate(11_289_600), 8_018);
+        let rates = SamplingRates::ADC272_DAC272_U;
+        assert!(rates.is_usb());
+        assert_eq!(rates.dac_rate(12_000_000), 44_118);
+        assert_eq!(
+            SamplingRates::ADC192_DAC192_D.nominal_core_clock_hz(),
+            16_934_400
+        );
+        assert_eq!(
+            SamplingRates::ADC1408_DAC256_B.nominal_core_clock_hz(),
+            11_289_600
+        );
+        assert_eq!(
+            SamplingRates::ADC576_DAC576_C.nominal_core_clock_hz(),
+            18_432_000
+        );
+        assert!(!SamplingRates::ADC128_DAC128_B.soft_mute_supported());
+        assert!(!SamplingRates::ADC192_DAC192_C.soft_mute_supported());
+        assert!(SamplingRates::ADC384_DAC384_A.soft_mute_supported());
+        let rates = unsafe { SamplingRates::from_raw_unchecked(0b010000) };
+        assert_eq!(rates.dac_rate(12_288_000), 0);
+    }
+}
@@ -1,13 +1,11 @@
+use crate::db::{self, Centibels, DbError, Rounding};
+use crate::gain::{Gain, ScaleError};
 use core::fmt;
+use core::ops::{Add, Sub};
+use core::str::FromStr;
 
 ///Error returned when trying to scale a value into SideAttdB.
-#[derive(Debug, Eq, PartialEq, Copy, Clone)]
-pub enum SideAttdBScaleError {
-    ///The given range is null.
-    NullRange,
-    ///The given value is outside the given range.
-    OutOfRange,
-}
+pub type SideAttdBScaleError = ScaleError;
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 /// Mic to output attenuation in dB. Beware, greater value mean greater attenuation, ie lower
@@ -51,35 +49,17 @@ impl SideAttdB {
         high_limit: i16,
         input: i16,
     ) -> Result<Self, SideAttdBScaleError> {
-        if low_limit == high_limit {
-            return Err(SideAttdBScaleError::NullRange);
-        } else if (low_limit < high_limit && (input < low_limit || input > high_limit))
-            || (low_limit > high_limit && (input > low_limit || input < high_limit))
-        {
-            return Err(SideAttdBScaleError::OutOfRange);
-        }
-
-        const MIN: u8 = SideAttdB::MIN.inner;
-        const MAX: u8 = SideAttdB::MAX.inner;
-        let r1 = high_limit - low_limit;
-        let r2 = MAX - MIN;
-        let res = (((input - low_limit) * r2 as i16) + r1 / 2) / (r1) + MIN as i16;
-        let res = unsafe { SideAttdB::from_raw_unchecked(res as _) };
-        Ok(res)
+        <Self as Gain>::from_scaled(low_limit, high_limit, input)
     }
 
     ///Increase the value by one step. Saturated to `SideAttdB::MAX`.
     pub fn increase(&mut self) {
-        if self.inner < SideAttdB::MAX.inner {
-            self.inner += 1;
-        }
+        Gain::increase(self)
     }
 
     ///Decrease the value by one step. Saturated to `SideAttdB::MIN`.
     pub fn decrease(&mut self) {
-        if self.inner > SideAttdB::MIN.inner {
-            self.inner -= 1;
-        }
+        Gain::decrease(self)
     }
     ///Represent a value of -6dB
     pub const N6DB: SideAttdB = unsafe { SideAttdB::from_raw_unchecked(0b00) };
@@ -94,6 +74,97 @@ impl SideAttdB {
     ///Maximum attenuation
     pub const MAX: SideAttdB = SideAttdB::N15DB;
 }
+/// dB conversions. Remember greater raw value means lower gain.
+impl SideAttdB {
+    const MIN_CB: Centibels = Centibels(-150);
+    const STEP_CB: i16 = 30;
+
+    /// Return the gain in centibels.
+    pub const fn to_centibels(self) -> Centibels {
+        Centibels(-60 - self.inner as i16 * Self::STEP_CB)
+    }
+
+    /// Return the step matching a gain. Saturate to the range.
+    pub fn from_centibels(cb: Centibels, rounding: Rounding) -> Self {
+        let index = db::step_index(
+            cb,
+            Self::MIN_CB,
+            Self::STEP_CB,
+            SideAttdB::MAX.inner,
+            rounding,
+        );
+        Self {
+            inner: SideAttdB::MAX.inner - index,
+        }
+    }
+
+    /// Return the step exactly matching a gain.
+    pub fn try_from_centibels(cb: Centibels) -> Result<Self, DbError> {
+        let index = db::exact_step_index(cb, Self::MIN_CB, Self::STEP_CB, SideAttdB::MAX.inner)?;
+        Ok(Self {
+            inner: SideAttdB::MAX.inner - index,
+        })
+    }
+}
+
+impl Gain for SideAttdB {
+    const MIN: Self = SideAttdB::MIN;
+    const MAX: Self = SideAttdB::MAX;
+    const STEP: Centibels = Centibels(-SideAttdB::STEP_CB);
+
+    unsafe fn from_raw_unchecked(raw: u8) -> Self {
+        Self { inner: raw }
+    }
+
+    fn into_raw(self) -> u8 {
+        self.inner
+    }
+
+    fn to_centibels(self) -> Option<Centibels> {
+        Some(self.to_centibels())
+    }
+
+    fn from_centibels(cb: Centibels, rounding: Rounding) -> Self {
+        SideAttdB::from_centibels(cb, rounding)
+    }
+}
+
+impl Add<Centibels> for SideAttdB {
+    type Output = SideAttdB;
+    /// Add a gain, saturating to the range.
+    fn add(self, rhs: Centibels) -> SideAttdB {
+        SideAttdB::from_centibels(self.to_centibels() + rhs, Rounding::Nearest)
+    }
+}
+
+impl Sub<Centibels> for SideAttdB {
+    type Output = SideAttdB;
+    /// Subtract a gain, saturating to the range.
+    fn sub(self, rhs: Centibels) -> SideAttdB {
+        self + -rhs
+    }
+}
+
+impl TryFrom<i16> for SideAttdB {
+    type Error = DbError;
+    /// Convert an integer number of dB. Fail if it doesn't match a step exactly.
+    fn try_from(db: i16) -> Result<Self, DbError> {
+        SideAttdB::try_from_centibels(Centibels::from_db(db))
+    }
+}
+
+impl FromStr for SideAttdB {
+    type Err = DbError;
+    /// Parse strings like `-12dB`, `+1.5dB` or `-3`. The value must match a step
+    /// exactly.
+    fn from_str(s: &str) -> Result<Self, DbError> {
+        match db::parse(s)? {
+            Some(cb) => SideAttdB::try_from_centibels(cb),
+            None => Err(DbError::NotRepresentable),
+        }
+    }
+}
+
 impl fmt::Display for SideAttdB {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let fmt = match self.inner {
@@ -150,4 +221,16 @@ mod tests {
             SideAttdB::MIN
         );
     }
+    #[test]
+    fn db_test() {
+        assert_eq!(SideAttdB::N9DB.to_centibels(), Centibels(-90));
+        let att = SideAttdB::from_centibels(Centibels(-100), Rounding::Up);
+        assert_eq!(att, SideAttdB::N9DB);
+        let att = SideAttdB::from_centibels(Centibels(-100), Rounding::Down);
+        assert_eq!(att, SideAttdB::N12DB);
+        assert_eq!(SideAttdB::N6DB + Centibels(30), SideAttdB::N6DB);
+        assert_eq!(SideAttdB::N6DB - Centibels(30), SideAttdB::N9DB);
+        assert_eq!(SideAttdB::try_from(-15), Ok(SideAttdB::N15DB));
+        assert_eq!("-12dB".parse::<SideAttdB>(), Ok(SideAttdB::N12DB));
+    }
 }
@@ -0,0 +1,65 @@
+//! Signal routing graph.
+//!
+//! Routing a signal through the codec involves several fields: `INSEL`, `BYPASS`, `DACSEL`,
+//! `SIDETONE`, `MUTEMIC`, line input mutes, `DACMU` and power down bits.
+//! [`Wm8731::connect`](crate::Wm8731::connect) and
+//! [`Wm8731::disconnect`](crate::Wm8731::disconnect) update all of them from a [`Source`] and a
+//! [`Sink`], and [`Wm8731::routes`](crate::Wm8731::routes) list the paths currently carrying
+//! signal with their gain.
+use core::fmt;
+
+use crate::db::Centibels;
+
+/// Signal entering the codec.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Source {
+    LineIn,
+    Mic,
+    Dac,
+}
+
+/// Signal leaving the codec.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Sink {
+    Adc,
+    /// Line and headphone outputs.
+    Outputs,
+}
+
+/// Every path the codec can route.
+pub const PATHS: [(Source, Sink); 5] = [
+    (Source::LineIn, Sink::Adc),
+    (Source::Mic, Sink::Adc),
+    (Source::LineIn, Sink::Outputs),
+    (Source::Mic, Sink::Outputs),
+    (Source::Dac, Sink::Outputs),
+];
+
+/// An active path.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct Route {
+    pub source: Source,
+    pub sink: Sink,
+    /// Gain of the left channel, `None` if muted. Gains to outputs include headphone volume, line
+    /// outputs don't go through it.
+    pub left: Option<Centibels>,
+    pub right: Option<Centibels>,
+}
+
+/// Error returned when a path doesn't exist in the codec.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct RoutingError {
+    pub source: Source,
+    pub sink: Sink,
+}
+
+impl fmt::Display for RoutingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no path from {:?} to {:?}", self.source, self.sink)
+    }
+}
+
+/// Returns `true` if the codec has a path from `source` to `sink`.
+pub fn is_routable(source: Source, sink: Sink) -> bool {
+    PATHS.contains(&(source, sink))
+}
@@ -0,0 +1,403 @@
+//! Sample packing and unpacking.
+//!
+//! The MCU audio peripheral transfers one word per channel slot, but where the sample bits sit
+//! inside the slot depends on the codec data format and word length. [`SampleLayout`] converts
+//! between PCM samples and those raw words.
+//!
+//! Inside this crate, a sample is a signed integer sign extended at the interface word length,
+//! ie in `-2^(bits-1)..2^(bits-1)`. Conversions from and to usual `i16` and `i32` PCM (full
+//! scale at type limits) are provided.
+//!
+//! In DSP mode the right sample immediately follow the left one, so with 20 or 24 bits samples in
+//! 32 bits slots a frame is packed as a single bitstream and the right sample straddles both
+//! slots.
+//!
+//! `LRSWAP` only swaps DAC channels, so it is applied when packing but not when unpacking. `LRP`
+//! is not handled here, the peripheral must be configured with the same clock polarity, see
+//! [`LinkDescriptor`](crate::link::LinkDescriptor).
+use crate::interface::WriteFrame;
+use crate::{FormatV, IwlV, Wm8731};
+
+/// Position of samples in peripheral words.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct SampleLayout {
+    format: FormatV,
+    iwl: IwlV,
+    lrswap: bool,
+    slot_bits: u8,
+}
+
+impl SampleLayout {
+    /// Layout with the smallest slot able to hold a sample: 16 bits for 16 bits samples, 32 bits
+    /// otherwise.
+    pub const fn new(format: FormatV, iwl: IwlV, lrswap: bool) -> Self {
+        let slot_bits = match iwl {
+            IwlV::Iwl16Bits => 16,
+            _ => 32,
+        };
+        Self {
+            format,
+            iwl,
+            lrswap,
+            slot_bits,
+        }
+    }
+
+    /// Layout matching the codec configuration.
+    pub fn from_codec<I: WriteFrame>(codec: &Wm8731<I>) -> Self {
+        Self::new(codec.format(), codec.iwl(), codec.lrswap())
+    }
+
+    /// Use another slot size, 16 or 32 bits. A slot smaller than the samples is ignored.
+    pub const fn with_slot_bits(self, slot_bits: u8) -> Self {
+        if (slot_bits == 16 || slot_bits == 32) && slot_bits >= self.iwl.bits() {
+            Self { slot_bits, ..self }
+        } else {
+            self
+        }
+    }
+
+    pub const fn format(&self) -> FormatV {
+        self.format
+    }
+
+    pub const fn iwl(&self) -> IwlV {
+        self.iwl
+    }
+
+    pub const fn lrswap(&self) -> bool {
+        self.lrswap
+    }
+
+    pub const fn slot_bits(&self) -> u8 {
+        self.slot_bits
+    }
+
+    /// Unused bits of a slot.
+    const fn padding(&self) -> u32 {
+        (self.slot_bits - self.iwl.bits()) as u32
+    }
+
+    const fn slot_mask(&self) -> u32 {
+        u32::MAX >> (32 - self.slot_bits as u32)
+    }
+
+    /// Place a sample in a slot word. Only the lowest `slot_bits` of the result are used.
+    ///
+    /// In right justified format, the sample is sign extended to the whole slot. In DSP mode only
+    /// the left sample is placed this way, see [`SampleLayout::pack_frame`].
+    pub const fn pack_sample(&self, sample: i32) -> u32 {
+        let word = match self.format {
+            FormatV::RigthJustified => sample as u32,
+            _ => (sample as u32) << self.padding(),
+        };
+        word & self.slot_mask()
+    }
+
+    /// Extract a sample from a slot word. In DSP mode only the left sample is placed this way, see
+    /// [`SampleLayout::unpack_frame`].
+    pub const fn unpack_sample(&self, word: u32) -> i32 {
+        // move the slot to the top of an i32, then shift the sample down with sign extension
+        let word = (word << (32 - self.slot_bits as u32)) as i32;
+        let sample = match self.format {
+            FormatV::RigthJustified => word << self.padding(),
+            _ => word,
+        };
+        sample >> (32 - self.iwl.bits() as u32)
+    }
+
+    /// Place a stereo frame, in slot order, in two slot words.
+    pub const fn pack_frame(&self, left: i32, right: i32) -> [u32; 2] {
+        match self.format {
+            FormatV::Dsp => {
+                let (bits, slot) = (self.iwl.bits() as u32, self.slot_bits as u32);
+                let mask = (1u64 << bits) - 1;
+                let stream = (left as u64 & mask) << (2 * slot - bits)
+                    | (right as u64 & mask) << (2 * slot - 2 * bits);
+                [
+                    (stream >> slot) as u32 & self.slot_mask(),
+                    stream as u32 & self.slot_mask(),
+                ]
+            }
+            _ => [self.pack_sample(left), self.pack_sample(right)],
+        }
+    }
+
+    /// Extract a stereo frame, in slot order, from two slot words.
+    pub const fn unpack_frame(&self, words: [u32; 2]) -> [i32; 2] {
+        match self.format {
+            FormatV::Dsp => {
+                let (bits, slot) = (self.iwl.bits() as u32, self.slot_bits as u32);
+                let stream = ((words[0] & self.slot_mask()) as u64) << slot
+                    | (words[1] & self.slot_mask()) as u64;
+                // move the frame to the top of an i64, then shift each sample down with sign
+                // extension
+                let frame = stream << (64 - 2 * slot);
+                [
+                    ((frame as i64) >> (64 - bits)) as i32,
+                    (((frame << bits) as i64) >> (64 - bits)) as i32,
+                ]
+            }
+            _ => [self.unpack_sample(words[0]), self.unpack_sample(words[1])],
+        }
+    }
+
+    /// Pack interleaved stereo samples into one word per slot. Return the number of words written.
+    pub fn pack(&self, samples: &[i32], words: &mut [u32]) -> usize {
+        let mut len = 0;
+        for (frame, out) in samples.chunks_exact(2).zip(words.chunks_exact_mut(2)) {
+            let (left, right) = self.dac_order(frame[0], frame[1]);
+            out.copy_from_slice(&self.pack_frame(left, right));
+            len += 2;
+        }
+        len
+    }
+
+    /// Unpack words into interleaved stereo samples. Return the number of samples written.
+    pub fn unpack(&self, words: &[u32], samples: &mut [i32]) -> usize {
+        let mut len = 0;
+        for (frame, out) in words.chunks_exact(2).zip(samples.chunks_exact_mut(2)) {
+            out.copy_from_slice(&self.unpack_frame([frame[0], frame[1]]));
+            len += 2;
+        }
+        len
+    }
+
+    /// Pack interleaved stereo `i16` PCM. Return the number of words written.
+    pub fn pack_i16(&self, pcm: &[i16], words: &mut [u32]) -> usize {
+        let mut len = 0;
+        for (frame, out) in pcm.chunks_exact(2).zip(words.chunks_exact_mut(2)) {
+            len += self.pack(&[self.from_i16(frame[0]), self.from_i16(frame[1])], out);
+        }
+        len
+    }
+
+    /// Unpack words into interleaved stereo `i16` PCM. Return the number of samples written.
+    pub fn unpack_i16(&self, words: &[u32], pcm: &mut [i16]) -> usize {
+        let mut len = 0;
+        for (frame, out) in words.chunks_exact(2).zip(pcm.chunks_exact_mut(2)) {
+            let [left, right] = self.unpack_frame([frame[0], frame[1]]);
+            out[0] = self.to_i16(left);
+            out[1] = self.to_i16(right);
+            len += 2;
+        }
+        len
+    }
+
+    /// Pack into 16 bits transfers, as done by peripherals like the STM32 SPI/I2S. 32 bits slots
+    /// are sent as two half words, most significant first. Return the number of half words
+    /// written.
+    pub fn pack_halfwords(&self, samples: &[i32], halfwords: &mut [u16]) -> usize {
+        let per_slot = self.halfwords_per_slot();
+        let mut len = 0;
+        for (frame, out) in samples
+            .chunks_exact(2)
+            .zip(halfwords.chunks_exact_mut(2 * per_slot))
+        {
+            let mut words = [0; 2];
+            self.pack(frame, &mut words);
+            for (word, out) in words.iter().zip(out.chunks_exact_mut(per_slot)) {
+                if per_slot == 2 {
+                    out[0] = (word >> 16) as u16;
+                    out[1] = *word as u16;
+                } else {
+                    out[0] = *word as u16;
+                }
+            }
+            len += 2 * per_slot;
+        }
+        len
+    }
+
+    /// Unpack 16 bits transfers, see [`SampleLayout::pack_halfwords`]. Return the number of
+    /// samples written.
+    pub fn unpack_halfwords(&self, halfwords: &[u16], samples: &mut [i32]) -> usize {
+        let per_slot = self.halfwords_per_slot();
+        let mut len = 0;
+        for (frame, out) in halfwords
+            .chunks_exact(2 * per_slot)
+            .zip(samples.chunks_exact_mut(2))
+        {
+            let mut words = [0; 2];
+            for (slot, word) in frame.chunks_exact(per_slot).zip(words.iter_mut()) {
+                *word = match slot {
+                    [high, low] => (*high as u32) << 16 | *low as u32,
+                    _ => slot[0] as u32,
+                };
+            }
+            out.copy_from_slice(&self.unpack_frame(words));
+            len += 2;
+        }
+        len
+    }
+
+    const fn halfwords_per_slot(&self) -> usize {
+        self.slot_bits as usize / 16
+    }
+
+    fn dac_order(&self, left: i32, right: i32) -> (i32, i32) {
+        if self.lrswap {
+            (right, left)
+        } else {
+            (left, right)
+        }
+    }
+
+    /// Convert `i16` PCM to a sample at the layout word length.
+    pub const fn from_i16(&self, pcm: i16) -> i32 {
+        (pcm as i32) << (self.iwl.bits() - 16)
+    }
+
+    /// Convert a sample at the layout word length to `i16` PCM, truncating extra bits.
+    pub const fn to_i16(&self, sample: i32) -> i16 {
+        (sample >> (self.iwl.bits() - 16)) as i16
+    }
+
+    /// Convert `i32` PCM to a sample at the layout word length, truncating extra bits.
+    pub const fn from_i32(&self, pcm: i32) -> i32 {
+        pcm >> (32 - self.iwl.bits())
+    }
+
+    /// Convert a sample at the layout word length to `i32` PCM.
+    pub const fn to_i32(&self, sample: i32) -> i32 {
+        ((sample as u32) << (32 - self.iwl.bits())) as i32
+    }
+}
+
+/// Interleave two channels. Return the number of samples written.
+pub fn interleave<T: Copy>(left: &[T], right: &[T], out: &mut [T]) -> usize {
+    let mut len = 0;
+    for ((l, r), frame) in left.iter().zip(right).zip(out.chunks_exact_mut(2)) {
+        frame[0] = *l;
+        frame[1] = *r;
+        len += 2;
+    }
+    len
+}
+
+/// Split interleaved samples into two channels. Return the number of frames written.
+pub fn deinterleave<T: Copy>(input: &[T], left: &mut [T], right: &mut [T]) -> usize {
+    let mut len = 0;
+    for ((frame, l), r) in input
+        .chunks_exact(2)
+        .zip(left.iter_mut())
+        .zip(right.iter_mut())
+    {
+        *l = frame[0];
+        *r = frame[1];
+        len += 1;
+    }
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORMATS: [FormatV; 4] = [
+        FormatV::Dsp,
+        FormatV::I2s,
+        FormatV::LeftJustified,
+        FormatV::RigthJustified,
+    ];
+    const IWLS: [IwlV; 4] = [
+        IwlV::Iwl16Bits,
+        IwlV::Iwl20Bits,
+        IwlV::Iwl24Bits,
+        IwlV::Iwl32Bits,
+    ];
+
+    #[test]
+    fn position_test() {
+        let layout = SampleLayout::new(FormatV::I2s, IwlV::Iwl24Bits, false);
+        assert_eq!(layout.pack_sample(-1), 0xffff_ff00);
+        assert_eq!(layout.pack_sample(0x12_3456), 0x1234_5600);
+        let layout = SampleLayout::new(FormatV::RigthJustified, IwlV::Iwl20Bits, false);
+        assert_eq!(layout.pack_sample(0x1_2345), 0x0001_2345);
+        assert_eq!(layout.pack_sample(-2), 0xffff_fffe);
+        let layout = SampleLayout::new(FormatV::LeftJustified, IwlV::Iwl16Bits, false);
+        assert_eq!(layout.pack_sample(-2), 0xfffe);
+        let layout = layout.with_slot_bits(32);
+        assert_eq!(layout.pack_sample(-2), 0xfffe_0000);
+        assert_eq!(layout.with_slot_bits(8).slot_bits(), 32);
+    }
+
+    #[test]
+    fn dsp_test() {
+        // right sample bits right after the left sample LSB
+        let layout = SampleLayout::new(FormatV::Dsp, IwlV::Iwl24Bits, false);
+        let mut words = [0; 2];
+        layout.pack(&[0x12_3456, -2], &mut words);
+        assert_eq!(words, [0x1234_56ff, 0xfffe_0000]);
+        let mut samples = [0; 2];
+        layout.unpack(&words, &mut samples);
+        assert_eq!(samples, [0x12_3456, -2]);
+        let mut halfwords = [0; 4];
+        layout.pack_halfwords(&[0x12_3456, -2], &mut halfwords);
+        assert_eq!(halfwords, [0x1234, 0x56ff, 0xfffe, 0x0000]);
+        let layout = SampleLayout::new(FormatV::Dsp, IwlV::Iwl20Bits, false);
+        layout.pack(&[0x1_2345, 0x6_789a], &mut words);
+        assert_eq!(words, [0x1234_5678, 0x9a00_0000]);
+        layout.unpack(&words, &mut samples);
+        assert_eq!(samples, [0x1_2345, 0x6_789a]);
+        let layout = SampleLayout::new(FormatV::Dsp, IwlV::Iwl16Bits, false).with_slot_bits(32);
+        layout.pack(&[0x1234, -1], &mut words);
+        assert_eq!(words, [0x1234_ffff, 0]);
+    }
+
+    #[test]
+    fn round_trip_test() {
+        for format in FORMATS {
+            for iwl in IWLS {
+                let layout = SampleLayout::new(format, iwl, false);
+                let max = (iwl.full_scale() - 1) as i32;
+                let samples = [max, -max - 1, 1, -1];
+                let mut words = [0; 4];
+                assert_eq!(layout.pack(&samples, &mut words), 4);
+                let mut back = [0; 4];
+                assert_eq!(layout.unpack(&words, &mut back), 4);
+                assert_eq!(back, samples, "{:?} {:?}", format, iwl);
+                let mut halfwords = [0; 8];
+                let len = layout.pack_halfwords(&samples, &mut halfwords);
+                assert_eq!(len, 4 * layout.slot_bits() as usize / 16);
+                let mut back = [0; 4];
+                assert_eq!(layout.unpack_halfwords(&halfwords[..len], &mut back), 4);
+                assert_eq!(back, samples, "{:?} {:?}", format, iwl);
+                let pcm = [i16::MAX, -3];
+                let mut words = [0; 2];
+                layout.pack_i16(&pcm, &mut words);
+                let mut back = [0; 2];
+                layout.unpack_i16(&words, &mut back);
+                assert_eq!(back, pcm);
+                // incomplete frame is left out
+                let mut back = [0; 4];
+                let slots = 3 * layout.slot_bits() as usize / 16;
+                assert_eq!(layout.unpack_halfwords(&halfwords[..slots], &mut back), 2);
+                assert_eq!(back, [max, -max - 1, 0, 0]);
+                assert_eq!(layout.from_i32(layout.to_i32(-5)), -5);
+            }
+        }
+    }
+
+    #[test]
+    fn swap_test() {
+        let layout = SampleLayout::new(FormatV::I2s, IwlV::Iwl16Bits, true);
+        let mut words = [0; 2];
+        layout.pack(&[1, 2], &mut words);
+        assert_eq!(words, [2, 1]);
+        // ADC data is not swapped
+        let mut samples = [0; 2];
+        layout.unpack(&words, &mut samples);
+        assert_eq!(samples, [2, 1]);
+    }
+
+    #[test]
+    fn interleave_test() {
+        let mut out = [0; 6];
+        assert_eq!(interleave(&[1, 3, 5], &[2, 4], &mut out), 4);
+        assert_eq!(out, [1, 2, 3, 4, 0, 0]);
+        let (mut left, mut right) = ([0; 3], [0; 3]);
+        assert_eq!(deinterleave(&[1, 2, 3, 4, 5], &mut left, &mut right), 2);
+        assert_eq!((left, right), ([1, 3, 0], [2, 4, 0]));
+    }
+}
@@ -0,0 +1,637 @@
+//! Named scenes and pop free transitions.
+//!
+//! A [`Scene`] gathers routing, powered blocks and gains of a use of the codec. Scenes are stored
+//! by name in a fixed capacity [`SceneBank`], and [`SceneTransition`] switch from the current
+//! configuration to a scene in order: headphone and line input fade out, DAC and input mutes,
+//! then routing and power changes while nothing can be heard, then fade in. Line outputs aren't
+//! attenuated by the headphone volume, they are silenced by the mutes. `MUTEMIC` only mutes the
+//! microphone to the ADC, so the sidetone is not silenced on line outputs. Like [`FadeEngine`], a
+//! transition doesn't block and is advanced by calling [`SceneTransition::tick`].
+//!
+//! Times are in milliseconds, from any free running wrapping counter.
+use core::fmt;
+
+use crate::fade::{FadeEngine, FadeTarget};
+use crate::interface::WriteFrame;
+use crate::power::{self, PathRouting, PowerProfile, UseCase};
+use crate::{HpVoldB, InVoldB, InselV, SideAttdB, Wm8731};
+
+/// Number of scenes a bank can hold.
+pub const SCENE_CAPACITY: usize = 8;
+
+/// Maximum length of a scene name, in bytes. Longer names are truncated.
+pub const SCENE_NAME_LEN: usize = 16;
+
+/// Codec configuration for a use.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct Scene {
+    name: [u8; SCENE_NAME_LEN],
+    name_len: u8,
+    pub routing: PathRouting,
+    pub power: PowerProfile,
+    /// Left and right headphone volumes.
+    pub hpvol: (HpVoldB, HpVoldB),
+    /// Left and right line input volumes.
+    pub invol: (InVoldB, InVoldB),
+    pub micboost: bool,
+    pub sideatt: SideAttdB,
+}
+
+impl Scene {
+    /// DAC to outputs.
+    pub const PLAYBACK: Scene = Scene::from_use_case("playback", UseCase::Playback);
+
+    /// DAC and microphone sidetone to outputs.
+    pub const KARAOKE: Scene = Scene {
+        routing: PathRouting {
+            dacsel: true,
+            bypass: false,
+            insel: InselV::Mic,
+            sidetone: true,
+        },
+        power: PowerProfile {
+            mic: true,
+            dac: true,
+            outputs: true,
+            ..PowerProfile::NONE
+        },
+        micboost: true,
+        sideatt: SideAttdB::N6DB,
+        ..Scene::from_use_case("karaoke", UseCase::Playback)
+    };
+
+    /// Line input to outputs through `BYPASS`, and recorded.
+    pub const LINE_MONITOR: Scene = Scene {
+        routing: PathRouting {
+            bypass: true,
+            ..UseCase::RecordLine.routing()
+        },
+        power: PowerProfile {
+            outputs: true,
+            ..UseCase::RecordLine.power_profile()
+        },
+        ..Scene::from_use_case("line monitor", UseCase::RecordLine)
+    };
+
+    /// Microphone recorded, DAC to outputs with a low sidetone.
+    pub const CALL: Scene = Scene {
+        routing: PathRouting {
+            dacsel: true,
+            bypass: false,
+            insel: InselV::Mic,
+            sidetone: true,
+        },
+        power: PowerProfile {
+            mic: true,
+            adc: true,
+            dac: true,
+            outputs: true,
+            line_in: false,
+        },
+        micboost: true,
+        sideatt: SideAttdB::N15DB,
+        ..Scene::from_use_case("call", UseCase::Duplex)
+    };
+
+    /// Scene using the routing and power profile of a use case, at 0dB without `MICBOOST`.
+    pub const fn from_use_case(name: &str, use_case: UseCase) -> Self {
+        let mut scene = Scene {
+            name: [0; SCENE_NAME_LEN],
+            name_len: 0,
+            routing: use_case.routing(),
+            power: use_case.power_profile(),
+            hpvol: (HpVoldB::Z0DB, HpVoldB::Z0DB),
+            invol: (InVoldB::Z0DB, InVoldB::Z0DB),
+            micboost: false,
+            sideatt: SideAttdB::N6DB,
+        };
+        let bytes = name.as_bytes();
+        let mut len = 0;
+        while len < bytes.len() && len < SCENE_NAME_LEN {
+            scene.name[len] = bytes[len];
+            len += 1;
+        }
+        scene.name_len = len as u8;
+        scene
+    }
+
+    /// Scene from the current codec configuration.
+    pub fn from_codec<I: WriteFrame>(name: &str, codec: &Wm8731<I>) -> Self {
+        Scene {
+            routing: codec.routing(),
+            power: codec.power_profile(),
+            hpvol: codec.both_hpvol(),
+            invol: codec.both_invol(),
+            micboost: codec.micboost(),
+            sideatt: codec.sideatt(),
+            ..Scene::from_use_case(name, UseCase::Standby)
+        }
+    }
+
+    /// Same scene with another name.
+    pub const fn with_name(self, name: &str) -> Self {
+        let named = Scene::from_use_case(name, UseCase::Standby);
+        Scene {
+            name: named.name,
+            name_len: named.name_len,
+            ..self
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        // names are built from str, truncation may only split a multibyte character
+        let bytes = &self.name[..self.name_len as usize];
+        match core::str::from_utf8(bytes) {
+            Ok(name) => name,
+            Err(error) => core::str::from_utf8(&bytes[..error.valid_up_to()]).unwrap_or(""),
+        }
+    }
+
+    /// Returns `true` if switching to this scene from the codec requires muting: routing, power
+    /// or input gains change.
+    pub fn needs_mute<I: WriteFrame>(&self, codec: &Wm8731<I>) -> bool {
+        self.routing != codec.routing()
+            || self.power != codec.power_profile()
+            || self.micboost != codec.micboost()
+            || self.sideatt != codec.sideatt()
+            || codec.both_invol() != self.invol
+            || codec.both_inmute() != (self.line_muted(), self.line_muted())
+            || codec.mutemic() != self.mic_muted()
+            || codec.dacmu() != self.dac_muted()
+    }
+
+    fn line_muted(&self) -> bool {
+        !self.power.line_in
+    }
+
+    fn mic_muted(&self) -> bool {
+        !(self.power.mic && self.power.adc && self.routing.insel == InselV::Mic)
+    }
+
+    fn dac_muted(&self) -> bool {
+        !(self.power.dac && self.routing.dacsel)
+    }
+
+    /// Write routing, power and input gains, with line input volumes at `invol`. Blocks are
+    /// powered before routing and powered down after.
+    fn apply_paths<I: WriteFrame>(&self, codec: &mut Wm8731<I>, invol: (InVoldB, InVoldB)) {
+        let current = codec.power_profile();
+        let union = PowerProfile {
+            line_in: current.line_in || self.power.line_in,
+            mic: current.mic || self.power.mic,
+            adc: current.adc || self.power.adc,
+            dac: current.dac || self.power.dac,
+            outputs: current.outputs || self.power.outputs,
+        };
+        codec.write_power_profile(union);
+        if codec.routing() != self.routing {
+            codec.set_routing(self.routing);
+        }
+        if codec.micboost() != self.micboost {
+            codec.set_micboost(self.micboost);
+        }
+        if codec.sideatt() != self.sideatt {
+            codec.set_sideatt(self.sideatt);
+        }
+        if codec.both_invol() != invol {
+            if invol.0 == invol.1 {
+                codec.set_both_invol(invol.0);
+            } else {
+                codec.set_left_invol(invol.0);
+                codec.set_right_invol(invol.1);
+            }
+        }
+        codec.write_power_profile(self.power);
+    }
+
+    /// Release mutes needed by the scene.
+    fn unmute<I: WriteFrame>(&self, codec: &mut Wm8731<I>) {
+        if codec.both_inmute() != (self.line_muted(), self.line_muted()) {
+            codec.set_both_inmute(self.line_muted());
+        }
+        if codec.mutemic() != self.mic_muted() {
+            codec.set_mutemic(self.mic_muted());
+        }
+        if codec.dacmu() != self.dac_muted() {
+            codec.set_dacmu(self.dac_muted());
+        }
+    }
+}
+
+/// Error returned by [`SceneBank`] operations.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum SceneError {
+    /// The bank holds [`SCENE_CAPACITY`] scenes.
+    Full,
+    /// A scene with the same name is already stored.
+    Duplicate,
+    /// No scene has this name.
+    NotFound,
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Full => f.write_str("scene bank is full"),
+            SceneError::Duplicate => f.write_str("a scene with this name already exists"),
+            SceneError::NotFound => f.write_str("no scene with this name"),
+        }
+    }
+}
+
+/// Fixed capacity scene storage.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct SceneBank {
+    scenes: [Option<Scene>; SCENE_CAPACITY],
+}
+
+impl Default for SceneBank {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SceneBank {
+    pub const fn new() -> Self {
+        Self {
+            scenes: [None; SCENE_CAPACITY],
+        }
+    }
+
+    /// Bank holding playback, karaoke, line monitor and call scenes.
+    pub const fn with_presets() -> Self {
+        let mut bank = Self::new();
+        bank.scenes[0] = Some(Scene::PLAYBACK);
+        bank.scenes[1] = Some(Scene::KARAOKE);
+        bank.scenes[2] = Some(Scene::LINE_MONITOR);
+        bank.scenes[3] = Some(Scene::CALL);
+        bank
+    }
+
+    /// Store a scene.
+    pub fn add(&mut self, scene: Scene) -> Result<(), SceneError> {
+        if self.get(scene.name()).is_some() {
+            return Err(SceneError::Duplicate);
+        }
+        let slot = self
+            .scenes
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(SceneError::Full)?;
+        *slot = Some(scene);
+        Ok(())
+    }
+
+    /// Store a scene, replacing the one with the same name.
+    pub fn replace(&mut self, scene: Scene) -> Result<(), SceneError> {
+        match self.get_mut(scene.name()) {
+            Some(stored) => {
+                *stored = scene;
+                Ok(())
+            }
+            None => self.add(scene),
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<Scene, SceneError> {
+        let slot = self
+            .scenes
+            .iter_mut()
+            .find(|slot| matches!(slot, Some(scene) if scene.name() == name))
+            .ok_or(SceneError::NotFound)?;
+        slot.take().ok_or(SceneError::NotFound)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Scene> {
+        self.iter().find(|scene| scene.name() == name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Scene> {
+        self.scenes
+            .iter_mut()
+            .flatten()
+            .find(|scene| scene.name() == name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Scene> {
+        self.scenes.iter().flatten()
+    }
+
+    /// Start a transition to a stored scene.
+    pub fn switch<I>(
+        &self,
+        codec: &Wm8731<I>,
+        name: &str,
+        fade_ms: u32,
+        now: u32,
+    ) -> Result<SceneTransition, SceneError>
+    where
+        I: WriteFrame,
+    {
+        let scene = self.get(name).ok_or(SceneError::NotFound)?;
+        Ok(SceneTransition::start(codec, *scene, fade_ms, now))
+    }
+}
+
+/// Step of a [`SceneTransition`].
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum TransitionPhase {
+    /// Headphone volume fading down to mute, and line input volume to its minimum if unmuted.
+    FadeOut,
+    /// DAC and inputs muted, waiting for the DAC soft mute.
+    SoftMute,
+    /// Headphone and line input volumes fading up to the scene volumes, after rerouting and
+    /// unmuting.
+    FadeIn,
+    Done,
+}
+
+/// Ordered switch to a scene.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct SceneTransition {
+    target: Scene,
+    phase: TransitionPhase,
+    fades: FadeEngine,
+    fade_ms: u32,
+    phase_ms: u32,
+}
+
+impl SceneTransition {
+    /// Plan the transition from the codec configuration to `target`. Fades last `fade_ms` each.
+    ///
+    /// If only the headphone volume differs, the transition is a single fade.
+    pub fn start<I>(codec: &Wm8731<I>, target: Scene, fade_ms: u32, now: u32) -> Self
+    where
+        I: WriteFrame,
+    {
+        let mut transition = Self {
+            target,
+            phase: TransitionPhase::FadeIn,
+            fades: FadeEngine::new(),
+            fade_ms,
+            phase_ms: now,
+        };
+        if target.needs_mute(codec) {
+            transition.phase = TransitionPhase::FadeOut;
+            transition.fade_headphone(codec, (HpVoldB::MUTE, HpVoldB::MUTE), now);
+            if !codec.lineinpd() && codec.both_inmute() != (true, true) {
+                transition.fade_line(codec, (InVoldB::MIN, InVoldB::MIN), now);
+            }
+        } else {
+            transition.fade_headphone(codec, target.hpvol, now);
+        }
+        transition
+    }
+
+    pub fn target(&self) -> &Scene {
+        &self.target
+    }
+
+    pub fn phase(&self) -> TransitionPhase {
+        self.phase
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.phase == TransitionPhase::Done
+    }
+
+    /// Advance the transition. Return the current phase.
+    pub fn tick<I>(&mut self, codec: &mut Wm8731<I>, now: u32) -> TransitionPhase
+    where
+        I: WriteFrame,
+    {
+        match self.phase {
+            TransitionPhase::FadeOut => {
+                self.fades.tick(codec, now);
+                if self.fades.is_idle() {
+                    if !codec.dacmu() {
+                        codec.set_dacmu(true);
+                    }
+                    if codec.both_inmute() != (true, true) {
+                        codec.set_both_inmute(true);
+                    }
+                    if !codec.mutemic() {
+                        codec.set_mutemic(true);
+                    }
+                    self.phase = TransitionPhase::SoftMute;
+                    self.phase_ms = now;
+                }
+            }
+            TransitionPhase::SoftMute => {
+                if now.wrapping_sub(self.phase_ms) >= power::SOFT_MUTE_MS as u32 {
+                    let line_fade = !self.target.line_muted();
+                    let invol = match line_fade {
+                        true => (InVoldB::MIN, InVoldB::MIN),
+                        false => self.target.invol,
+                    };
+                    self.target.apply_paths(codec, invol);
+                    self.target.unmute(codec);
+                    self.fade_headphone(codec, self.target.hpvol, now);
+                    if line_fade {
+                        self.fade_line(codec, self.target.invol, now);
+                    }
+                    self.phase = TransitionPhase::FadeIn;
+                }
+            }
+            TransitionPhase::FadeIn => {
+                self.fades.tick(codec, now);
+                if self.fades.is_idle() {
+                    self.phase = TransitionPhase::Done;
+                }
+            }
+            TransitionPhase::Done => (),
+        }
+        self.phase
+    }
+
+    fn fade_headphone<I>(&mut self, codec: &Wm8731<I>, volume: (HpVoldB, HpVoldB), now: u32)
+    where
+        I: WriteFrame,
+    {
+        self.fades.start(
+            codec,
+            FadeTarget::LeftHeadphone(volume.0),
+            self.fade_ms,
+            now,
+        );
+        self.fades.start(
+            codec,
+            FadeTarget::RightHeadphone(volume.1),
+            self.fade_ms,
+            now,
+        );
+    }
+
+    fn fade_line<I>(&mut self, codec: &Wm8731<I>, volume: (InVoldB, InVoldB), now: u32)
+    where
+        I: WriteFrame,
+    {
+        self.fades
+            .start(codec, FadeTarget::LeftLineIn(volume.0), self.fade_ms, now);
+        self.fades
+            .start(codec, FadeTarget::RightLineIn(volume.1), self.fade_ms, now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{ClockConfig, ClockSource};
+    use crate::interface::mock::{FrameLog, NoDelay};
+    use crate::sim::{InputFrame, Simulator};
+    use crate::SamplingRates;
+
+    fn run<I: WriteFrame>(transition: &mut SceneTransition, codec: &mut Wm8731<I>) -> u32 {
+        let mut now = 0;
+        while !transition.is_done() && now < 10_000 {
+            now += 1;
+            transition.tick(codec, now);
+        }
+        now
+    }
+
+    #[test]
+    fn bank_test() {
+        let mut bank = SceneBank::with_presets();
+        assert_eq!(bank.len(), 4);
+        assert_eq!(bank.get("karaoke"), Some(&Scene::KARAOKE));
+        assert_eq!(bank.add(Scene::CALL), Err(SceneError::Duplicate));
+        let long = Scene::PLAYBACK.with_name("a name longer than sixteen bytes");
+        assert_eq!(long.name(), "a name longer th");
+        for i in 0..4u8 {
+            let name = [b'a' + i];
+            let scene = Scene::PLAYBACK.with_name(core::str::from_utf8(&name).unwrap());
+            bank.add(scene).unwrap();
+        }
+        assert_eq!(bank.add(long), Err(SceneError::Full));
+        assert!(bank.remove("call").is_ok());
+        assert_eq!(bank.remove("call"), Err(SceneError::NotFound));
+        bank.add(long).unwrap();
+        let mut louder = Scene::PLAYBACK;
+        louder.hpvol = (HpVoldB::P6DB, HpVoldB::P6DB);
+        bank.replace(louder).unwrap();
+        assert_eq!(
+            bank.get("playback").map(|scene| scene.hpvol),
+            Some((HpVoldB::P6DB, HpVoldB::P6DB))
+        );
+    }
+
+    #[test]
+    fn transition_test() {
+        let mut codec = Wm8731::new(FrameLog::new());
+        codec.power_up_use_case(UseCase::Bypass, &mut NoDelay::default());
+        codec.set_both_inmute(false);
+        codec.set_both_hpvol(HpVoldB::N6DB, false);
+        let bank = SceneBank::with_presets();
+        let mut transition = bank.switch(&codec, "playback", 100, 0).unwrap();
+        assert_eq!(transition.phase(), TransitionPhase::FadeOut);
+        // routing is never changed while the headphone is audible
+        let mut now = 0;
+        while transition.phase() == TransitionPhase::FadeOut {
+            now += 1;
+            transition.tick(&mut codec, now);
+            assert!(codec.bypass());
+        }
+        assert_eq!(codec.both_hpvol(), (HpVoldB::MUTE, HpVoldB::MUTE));
+        assert_eq!(codec.both_inmute(), (true, true));
+        assert!(codec.dacmu());
+        while transition.phase() == TransitionPhase::SoftMute {
+            now += 1;
+            transition.tick(&mut codec, now);
+        }
+        assert!(now >= power::SOFT_MUTE_MS as u32 + 100);
+        assert!(!codec.bypass() && codec.dacsel() && !codec.dacmu());
+        assert_eq!(codec.power_profile(), UseCase::Playback.power_profile());
+        run(&mut transition, &mut codec);
+        assert_eq!(codec.both_hpvol(), (HpVoldB::Z0DB, HpVoldB::Z0DB));
+        assert!(!Scene::PLAYBACK.needs_mute(&codec));
+
+        // volume only change is a single fade
+        let mut louder = Scene::PLAYBACK;
+        louder.hpvol = (HpVoldB::P6DB, HpVoldB::P6DB);
+        let mut transition = SceneTransition::start(&codec, louder, 20, 0);
+        assert_eq!(transition.phase(), TransitionPhase::FadeIn);
+        codec.interface.clear();
+        run(&mut transition, &mut codec);
+        assert!(codec
+            .interface
+            .frames()
+            .iter()
+            .all(|frame| frame >> 9 == 0b10));
+
+        let mut transition = SceneTransition::start(&codec, Scene::KARAOKE, 20, 0);
+        run(&mut transition, &mut codec);
+        assert!(codec.sidetone() && codec.dacsel() && codec.micboost());
+        assert!(codec.mutemic());
+        assert_eq!(codec.both_inmute(), (true, true));
+    }
+
+    #[test]
+    fn balance_test() {
+        let mut codec = Wm8731::new(FrameLog::new());
+        codec.power_up_use_case(UseCase::Duplex, &mut NoDelay::default());
+        codec.set_left_hpvol(HpVoldB::N6DB, false);
+        codec.set_right_hpvol(HpVoldB::N12DB, false);
+        codec.set_left_invol(InVoldB::P3DB);
+        let scene = Scene::from_codec("balanced", &codec);
+        assert_eq!(scene.hpvol, (HpVoldB::N6DB, HpVoldB::N12DB));
+        assert_eq!(scene.invol, (InVoldB::P3DB, InVoldB::Z0DB));
+        let mut transition = SceneTransition::start(&codec, Scene::PLAYBACK, 20, 0);
+        run(&mut transition, &mut codec);
+        let mut transition = SceneTransition::start(&codec, scene, 20, 0);
+        run(&mut transition, &mut codec);
+        assert_eq!(codec.both_hpvol(), scene.hpvol);
+        assert_eq!(codec.both_invol(), scene.invol);
+    }
+
+    #[test]
+    fn line_out_test() {
+        let mut codec = Wm8731::new(FrameLog::new());
+        codec.set_sampling_rates(SamplingRates::ADC256_DAC256_A);
+        codec.set_clock_config(ClockConfig::new(ClockSource::ExternalMclk {
+            hz: 12_288_000,
+        }));
+        codec.power_up_use_case(UseCase::Bypass, &mut NoDelay::default());
+        codec.set_both_inmute(false);
+        let mut sim = Simulator::from_codec(&codec).unwrap();
+        // line input and DAC at a quarter of full scale
+        let level = 1 << 29;
+        let input = InputFrame {
+            line: [level; 2],
+            mic: 0,
+            dac: [1 << 21; 2],
+        };
+        let mut transition = SceneTransition::start(&codec, Scene::PLAYBACK, 100, 0);
+        let mut previous = sim.step(input).line_out;
+        assert_eq!(previous, [level; 2]);
+        let mut now = 0;
+        while !transition.is_done() && now < 10_000 {
+            now += 1;
+            let routing = codec.routing();
+            transition.tick(&mut codec, now);
+            if codec.routing() != routing {
+                // rerouted while line outputs are silent
+                assert_eq!(previous, [0; 2]);
+            }
+            sim.sync_codec(&codec);
+            // 1ms at 48kHz
+            for _ in 0..48 {
+                let line_out = sim.step(input).line_out;
+                for (out, last) in line_out.iter().zip(previous) {
+                    assert!(out.abs_diff(last) < level as u32 / 4);
+                }
+                previous = line_out;
+            }
+        }
+        assert!(transition.is_done() && codec.dacsel() && !codec.bypass());
+        assert_eq!(previous, [level; 2]);
+    }
+}
@@ -0,0 +1,366 @@
+//! Loopback self-test.
+//!
+//! The WM8731 has no internal path from DAC to ADC, so the outputs (line or headphone) must be
+//! looped back to line inputs, by a test fixture cable or a relay on the board. [`run_loopback`]
+//! then route the codec for duplex operation, play tones generated by
+//! [`Generator`](crate::generator::Generator) on each channel in turn and analyse the captured
+//! audio, giving gain, distortion, noise floor and crosstalk of each channel, and whether left
+//! and right are swapped somewhere in the loop.
+//!
+//! Audio goes through the [`SampleIo`] trait, so the routine can run on the host against a
+//! simulated loop.
+use core::fmt;
+
+use crate::db::Centibels;
+use crate::generator::{Generator, Waveform};
+use crate::interface::WriteFrame;
+use crate::math;
+use crate::meter::{self, ToneAnalysis};
+use crate::samples;
+use crate::{IwlV, UseCase, Wm8731};
+
+/// Full duplex audio transfers.
+///
+/// Samples are interleaved stereo, sign extended at the interface word length.
+pub trait SampleIo {
+    type Error;
+    /// Play a block and capture a block of the same length.
+    fn transfer(&mut self, playback: &[i32], capture: &mut [i32]) -> Result<(), Self::Error>;
+}
+
+/// Self-test parameters.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct SelfTestConfig {
+    /// Tone frequency. Blocks should hold a whole number of periods.
+    pub frequency_hz: u32,
+    /// Tone peak level in dBFS.
+    pub level: Centibels,
+    /// Blocks discarded after each stimulus change, covering loop latency and filters settling.
+    pub settle_blocks: u8,
+}
+
+impl SelfTestConfig {
+    /// 1kHz at -12dBFS.
+    pub const DEFAULT: SelfTestConfig = SelfTestConfig {
+        frequency_hz: 1000,
+        level: Centibels(-120),
+        settle_blocks: 4,
+    };
+}
+
+impl Default for SelfTestConfig {
+    fn default() -> Self {
+        SelfTestConfig::DEFAULT
+    }
+}
+
+/// Measurements of one DAC channel.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct ChannelReport {
+    /// Level captured relative to level played.
+    pub gain: Centibels,
+    pub thd_n: Centibels,
+    /// Level captured without stimulus, in dBFS.
+    pub noise_floor: Centibels,
+    /// Level captured on the other channel relative to this one.
+    pub crosstalk: Centibels,
+}
+
+/// Result of [`run_loopback`].
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct SelfTestReport {
+    pub left: ChannelReport,
+    pub right: ChannelReport,
+    /// Each channel is captured on the opposite side. Other reports fields follow DAC channels.
+    pub swapped: bool,
+}
+
+/// Error returned by [`run_loopback`].
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum SelfTestError<E> {
+    /// Audio transfer failed.
+    Io(E),
+    /// DAC sampling frequency unknown, see [`Wm8731::set_clock_config`].
+    UnknownRate,
+    /// Buffers are empty, of different length or not holding whole stereo frames.
+    BufferSize,
+}
+
+impl<E: fmt::Debug> fmt::Display for SelfTestError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SelfTestError::Io(error) => write!(f, "audio transfer failed: {:?}", error),
+            SelfTestError::UnknownRate => f.write_str("DAC sampling frequency is unknown"),
+            SelfTestError::BufferSize => f.write_str("invalid buffer size"),
+        }
+    }
+}
+
+/// Run the loopback self-test.
+///
+/// `playback` and `capture` are the block buffers, of the same length. The codec is switched to
+/// [`UseCase::Duplex`], line inputs and DAC are unmuted, and routing, mutes and powered blocks are
+/// restored before returning, even on error.
+pub fn run_loopback<I, S>(
+    codec: &mut Wm8731<I>,
+    io: &mut S,
+    config: SelfTestConfig,
+    playback: &mut [i32],
+    capture: &mut [i32],
+) -> Result<SelfTestReport, SelfTestError<S::Error>>
+where
+    I: WriteFrame,
+    S: SampleIo,
+{
+    let rate = codec.dac_rate_hz().ok_or(SelfTestError::UnknownRate)?;
+    if playback.is_empty() || playback.len() != capture.len() || playback.len() % 2 != 0 {
+        return Err(SelfTestError::BufferSize);
+    }
+    let routing = codec.routing();
+    let power = codec.power_profile();
+    let inmute = codec.both_inmute();
+    let dacmu = codec.dacmu();
+    codec.set_use_case(UseCase::Duplex);
+    if inmute != (false, false) {
+        codec.set_both_inmute(false);
+    }
+    if dacmu {
+        codec.set_dacmu(false);
+    }
+    let mut bench = Bench {
+        io,
+        config,
+        generator: Generator::new(
+            Waveform::Sine {
+                hz: config.frequency_hz,
+            },
+            config.level,
+            rate,
+            codec.iwl(),
+        ),
+        rate,
+        iwl: codec.iwl(),
+        playback,
+        capture,
+    };
+    let report = bench.measure();
+    codec.set_routing(routing);
+    if inmute != (false, false) {
+        codec.set_left_inmute(inmute.0);
+        codec.set_right_inmute(inmute.1);
+    }
+    if dacmu {
+        codec.set_dacmu(true);
+    }
+    codec.write_power_profile(power);
+    report
+}
+
+struct Bench<'a, S> {
+    io: &'a mut S,
+    config: SelfTestConfig,
+    generator: Generator,
+    rate: u32,
+    iwl: IwlV,
+    playback: &'a mut [i32],
+    capture: &'a mut [i32],
+}
+
+impl<S: SampleIo> Bench<'_, S> {
+    fn measure(&mut self) -> Result<SelfTestReport, SelfTestError<S::Error>> {
+        self.stimulate(None)?;
+        let noise = self.channels(math::rms);
+        let left = self.analyse(0)?;
+        let right = self.analyse(1)?;
+        let swapped =
+            left[1].amplitude > left[0].amplitude && right[0].amplitude > right[1].amplitude;
+        let report = |channel: usize, analysis: [ToneAnalysis; 2]| {
+            let (captured, other) = match swapped {
+                false => (channel, 1 - channel),
+                true => (1 - channel, channel),
+            };
+            ChannelReport {
+                gain: analysis[captured].level - self.config.level,
+                thd_n: analysis[captured].thd_n,
+                noise_floor: Centibels::from_ratio(noise[captured], self.iwl.full_scale()),
+                crosstalk: Centibels::from_ratio(
+                    analysis[other].amplitude,
+                    analysis[captured].amplitude,
+                ),
+            }
+        };
+        Ok(SelfTestReport {
+            left: report(0, left),
+            right: report(1, right),
+            swapped,
+        })
+    }
+
+    fn analyse(&mut self, channel: usize) -> Result<[ToneAnalysis; 2], SelfTestError<S::Error>> {
+        self.stimulate(Some(channel))?;
+        let (hz, rate, iwl) = (self.config.frequency_hz, self.rate, self.iwl);
+        Ok(self.channels(|samples| meter::analyze_tone(samples, hz, rate, iwl)))
+    }
+
+    /// Play the tone on `channel`, or silence, until the loop settled and keep the last capture.
+    fn stimulate(&mut self, channel: Option<usize>) -> Result<(), SelfTestError<S::Error>> {
+        self.generator.set_waveform(self.generator.waveform());
+        for _ in 0..=self.config.settle_blocks {
+            for frame in self.playback.chunks_exact_mut(2) {
+                frame[0] = 0;
+                frame[1] = 0;
+                if let Some(channel) = channel {
+                    frame[channel] = self.generator.next_sample();
+                }
+            }
+            self.io
+                .transfer(self.playback, self.capture)
+                .map_err(SelfTestError::Io)?;
+        }
+        Ok(())
+    }
+
+    /// Apply `f` to each captured channel. The playback buffer is used as scratch.
+    fn channels<T>(&mut self, mut f: impl FnMut(&[i32]) -> T) -> [T; 2] {
+        let (left, right) = self.playback.split_at_mut(self.capture.len() / 2);
+        let frames = samples::deinterleave(self.capture, left, right);
+        [f(&left[..frames]), f(&right[..frames])]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{ClockConfig, ClockSource};
+    use crate::interface::mock::FrameLog;
+    use crate::{InselV, SamplingRates};
+
+    /// Cable between outputs and line inputs.
+    struct SimulatedLoop {
+        gain_q16: [i64; 2],
+        swap: bool,
+        noise: i32,
+        seed: u32,
+    }
+
+    impl SampleIo for SimulatedLoop {
+        type Error = ();
+        fn transfer(&mut self, playback: &[i32], capture: &mut [i32]) -> Result<(), ()> {
+            for (played, captured) in playback.chunks_exact(2).zip(capture.chunks_exact_mut(2)) {
+                for (channel, (out, gain)) in captured.iter_mut().zip(self.gain_q16).enumerate() {
+                    let source = if self.swap { 1 - channel } else { channel };
+                    self.seed ^= self.seed << 13;
+                    self.seed ^= self.seed >> 17;
+                    self.seed ^= self.seed << 5;
+                    let noise = (self.seed % (2 * self.noise as u32 + 1)) as i32 - self.noise;
+                    *out = ((played[source] as i64 * gain) >> 16) as i32 + noise;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    fn clocked_codec() -> Wm8731<FrameLog> {
+        let mut codec = Wm8731::new(FrameLog::new());
+        codec.set_sampling_rates(SamplingRates::ADC256_DAC256_A);
+        codec.set_clock_config(ClockConfig::new(ClockSource::ExternalMclk {
+            hz: 12_288_000,
+        }));
+        codec
+    }
+
+    /// Audio transfer always failing.
+    struct BrokenLoop;
+
+    impl SampleIo for BrokenLoop {
+        type Error = ();
+        fn transfer(&mut self, _playback: &[i32], _capture: &mut [i32]) -> Result<(), ()> {
+            Err(())
+        }
+    }
+
+    #[test]
+    fn loopback_test() {
+        let mut codec = clocked_codec();
+        codec.set_use_case(UseCase::Playback);
+        let routing = codec.routing();
+        let power = codec.power_profile();
+        let mut io = SimulatedLoop {
+            // -6dB on left, 0dB on right
+            gain_q16: [32_846, 65_536],
+            swap: false,
+            noise: 2560,
+            seed: 1,
+        };
+        let (mut playback, mut capture) = ([0; 960], [0; 960]);
+        let report = run_loopback(
+            &mut codec,
+            &mut io,
+            SelfTestConfig::DEFAULT,
+            &mut playback,
+            &mut capture,
+        )
+        .unwrap();
+        assert!(!report.swapped);
+        assert!((report.left.gain.0 + 60).abs() <= 1);
+        assert!(report.right.gain.0.abs() <= 1);
+        // uniform noise of peak 2560 has a RMS of 1478, ie -75dBFS at 24 bits
+        assert!((report.left.noise_floor.0 + 751).abs() <= 10);
+        assert!(report.left.thd_n.0 < -500);
+        assert!(report.left.crosstalk.0 < -400);
+        assert_eq!(codec.routing(), routing);
+        assert_eq!(codec.power_profile(), power);
+        assert_eq!(codec.both_inmute(), (true, true));
+        assert_eq!(codec.insel(), InselV::Line);
+
+        io.swap = true;
+        let report = run_loopback(
+            &mut codec,
+            &mut io,
+            SelfTestConfig::DEFAULT,
+            &mut playback,
+            &mut capture,
+        )
+        .unwrap();
+        assert!(report.swapped);
+        // left DAC is captured on right ADC, at 0dB
+        assert!(report.left.gain.0.abs() <= 1);
+        assert!((report.right.gain.0 + 60).abs() <= 1);
+    }
+
+    #[test]
+    fn error_test() {
+        let mut io = SimulatedLoop {
+            gain_q16: [65_536; 2],
+            swap: false,
+            noise: 0,
+            seed: 1,
+        };
+        let (mut playback, mut capture) = ([0; 960], [0; 960]);
+        let config = SelfTestConfig::DEFAULT;
+        let mut codec = Wm8731::new(FrameLog::new());
+        let result = run_loopback(&mut codec, &mut io, config, &mut playback, &mut capture);
+        assert_eq!(result, Err(SelfTestError::UnknownRate));
+        let mut codec = clocked_codec();
+        let result = run_loopback(
+            &mut codec,
+            &mut io,
+            config,
+            &mut playback,
+            &mut capture[1..],
+        );
+        assert_eq!(result, Err(SelfTestError::BufferSize));
+        codec.set_use_case(UseCase::Playback);
+        let power = codec.power_profile();
+        let result = run_loopback(
+            &mut codec,
+            &mut BrokenLoop,
+            config,
+            &mut playback,
+            &mut capture,
+        );
+        assert_eq!(result, Err(SelfTestError::Io(())));
+        assert_eq!(codec.power_profile(), power);
+        assert!(!power.adc && !power.line_in);
+    }
+}
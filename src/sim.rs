@@ -0,0 +1,431 @@
+//! Behavioural simulation of the codec audio paths.
+//!
+//! [`Simulator`] takes the register state of a [`Wm8731`] driver, through a [`Snapshot`], and
+//! computes what the codec would output for given input signals: headphone and line outputs,
+//! and ADC samples. It models powered blocks, line input volume and mute, `MICBOOST`, `MUTEMIC`,
+//! input selection, `BYPASS`, `SIDETONE` and its attenuation, `DACSEL`, DAC soft mute,
+//! de-emphasis, the ADC high pass filter with its stored offset, `LRSWAP` and headphone volume.
+//! It is meant for host tests of whole features, so a wrong routing shows up as a missing or
+//! unexpected signal.
+//!
+//! Analogue signals are `i32` where `2^31` is the full scale of the line input and output, which
+//! is also the ADC and DAC full scale at 0dB. DAC and ADC samples are sign extended at the
+//! interface word length. Filters and ramps follow the datasheet behaviour, not its exact
+//! coefficients.
+use crate::db::Centibels;
+use crate::interface::WriteFrame;
+use crate::math;
+use crate::{AdcHighPass, DeempV, InselV, Snapshot, Wm8731};
+
+/// Gain of `MICBOOST`.
+const MICBOOST_Q16: i64 = 655_360;
+/// ADC high pass filter cut-off.
+const HPF_CUTOFF_HZ: i64 = 4;
+/// Soft mute ramp step per sample, in Q16, so a full ramp lasts 1024 samples.
+const SOFT_MUTE_STEP: i64 = 64;
+/// Analogue full scale.
+const FULL_SCALE: u32 = 1 << 31;
+
+/// Input signals of one frame.
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Default)]
+pub struct InputFrame {
+    pub line: [i32; 2],
+    pub mic: i32,
+    /// DAC samples, in interface slot order.
+    pub dac: [i32; 2],
+}
+
+/// Output signals of one frame.
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Default)]
+pub struct OutputFrame {
+    pub headphone: [i32; 2],
+    pub line_out: [i32; 2],
+    /// ADC samples.
+    pub adc: [i32; 2],
+}
+
+/// Peak levels since the last reset, in dBFS.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct SimLevels {
+    pub headphone: [Centibels; 2],
+    pub line_out: [Centibels; 2],
+    pub adc: [Centibels; 2],
+}
+
+/// Codec audio paths model.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct Simulator {
+    registers: Snapshot,
+    sample_rate_hz: u32,
+    /// De-emphasis input and output of the previous sample.
+    deemp: [(i64, i64); 2],
+    /// High pass filter input and output of the previous sample.
+    hpf: [(i64, i64); 2],
+    /// Offset stored by the high pass filter, used when it is disabled with `HPOR` set.
+    offset: [i64; 2],
+    /// DAC soft mute gain in Q16.
+    mute_gain: i64,
+    peaks: [[u32; 2]; 3],
+}
+
+impl Simulator {
+    pub fn new(registers: Snapshot, sample_rate_hz: u32) -> Self {
+        let mut simulator = Self {
+            registers,
+            sample_rate_hz: sample_rate_hz.max(1),
+            deemp: [(0, 0); 2],
+            hpf: [(0, 0); 2],
+            offset: [0; 2],
+            mute_gain: 0,
+            peaks: [[0; 2]; 3],
+        };
+        simulator.mute_gain = if registers.digital_audio_path.dacmu() {
+            0
+        } else {
+            1 << 16
+        };
+        simulator
+    }
+
+    /// Simulator of a codec, at its ADC sampling frequency. `None` if the clock configuration is
+    /// unknown.
+    pub fn from_codec<I: WriteFrame>(codec: &Wm8731<I>) -> Option<Self> {
+        Some(Self::new(codec.snapshot(), codec.adc_rate_hz()?))
+    }
+
+    /// Follow register changes, keeping filters and ramps state.
+    pub fn sync(&mut self, registers: Snapshot) {
+        self.registers = registers;
+    }
+
+    /// Follow register changes of a codec.
+    pub fn sync_codec<I: WriteFrame>(&mut self, codec: &Wm8731<I>) {
+        self.sync(codec.snapshot());
+    }
+
+    pub fn registers(&self) -> Snapshot {
+        self.registers
+    }
+
+    /// Compute one frame.
+    pub fn step(&mut self, input: InputFrame) -> OutputFrame {
+        let regs = &self.registers;
+        let power = &regs.power_down;
+        let path = &regs.analogue_audio_path;
+        let powered = !power.poweroff();
+        let active = regs.active.get();
+        let bits = regs.digital_audio_interface.iwl().bits() as u32;
+
+        // line input volume and mute, feeding ADC and bypass
+        let line_ins = [
+            (regs.left_line_in.vol(), regs.left_line_in.mute()),
+            (regs.right_line_in.vol(), regs.right_line_in.mute()),
+        ];
+        let mut line = [0i64; 2];
+        if powered && !power.lineinpd() {
+            for ((out, input), (vol, mute)) in line.iter_mut().zip(input.line).zip(line_ins) {
+                if !mute {
+                    *out = apply_gain(input as i64, Some(vol.to_centibels()));
+                }
+            }
+        }
+        let mut mic = 0i64;
+        if powered && !power.micpd() {
+            mic = input.mic as i64;
+            if path.micboost() {
+                mic = (mic * MICBOOST_Q16) >> 16;
+            }
+        }
+
+        // DAC with soft mute and de-emphasis
+        let target = if regs.digital_audio_path.dacmu() {
+            0
+        } else {
+            1 << 16
+        };
+        self.mute_gain += (target - self.mute_gain).clamp(-SOFT_MUTE_STEP, SOFT_MUTE_STEP);
+        let mut dac = [0i64; 2];
+        if powered && active && !power.dacpd() {
+            let mut slots = input.dac;
+            if regs.digital_audio_interface.lrswap() {
+                slots.swap(0, 1);
+            }
+            let deemp = deemphasis(regs.digital_audio_path.deemp());
+            for ((out, sample), state) in dac.iter_mut().zip(slots).zip(self.deemp.iter_mut()) {
+                let x = (((sample as i64) << (32 - bits)) * self.mute_gain) >> 16;
+                *out = match deemp {
+                    Some((b0, b1, a1)) => {
+                        let y = (b0 * x + b1 * state.0 - a1 * state.1) >> 30;
+                        *state = (x, y);
+                        y
+                    }
+                    None => x,
+                };
+            }
+        }
+
+        // output mixer
+        let mut output = OutputFrame::default();
+        if powered && !power.outpd() {
+            let hpvols = [regs.left_hpvol, regs.right_hpvol];
+            for channel in 0..2 {
+                let mut mix = 0;
+                if path.dacsel() {
+                    mix += dac[channel];
+                }
+                if path.bypass() {
+                    mix += line[channel];
+                }
+                if path.sidetone() {
+                    mix += apply_gain(mic, Some(path.sideatt().to_centibels()));
+                }
+                output.line_out[channel] = saturate(mix);
+                output.headphone[channel] =
+                    saturate(apply_gain(mix, hpvols[channel].to_centibels()));
+            }
+        }
+
+        // ADC with high pass filter
+        if powered && active && !power.adcpd() {
+            let adc_in = match path.insel() {
+                InselV::Line => line,
+                InselV::Mic if path.mutemic() => [0; 2],
+                InselV::Mic => [mic; 2],
+            };
+            let digital = &regs.digital_audio_path;
+            // 1 - 2*pi*fc/fs in Q30
+            let a = (1 << 30) - (6_746_518_852 * HPF_CUTOFF_HZ) / self.sample_rate_hz as i64;
+            for (channel, x) in adc_in.into_iter().enumerate() {
+                let x = saturate(x) as i64;
+                let y = match digital.adc_high_pass() {
+                    AdcHighPass::Enabled => {
+                        let (x1, y1) = self.hpf[channel];
+                        let y = (a * (y1 + x - x1)) >> 30;
+                        self.hpf[channel] = (x, y);
+                        self.offset[channel] = x - y;
+                        y
+                    }
+                    AdcHighPass::FrozenOffset => x - self.offset[channel],
+                    AdcHighPass::Disabled => x,
+                };
+                output.adc[channel] = saturate(y) >> (32 - bits);
+            }
+        }
+
+        for (peaks, signal) in
+            self.peaks
+                .iter_mut()
+                .zip([output.headphone, output.line_out, output.adc])
+        {
+            for (peak, value) in peaks.iter_mut().zip(signal) {
+                *peak = (*peak).max(value.unsigned_abs());
+            }
+        }
+        output
+    }
+
+    /// Process blocks. `line` and `dac` are interleaved stereo, `mic` is mono, and all are read
+    /// as silence past their end. Return the number of frames written to `adc`, interleaved
+    /// stereo.
+    pub fn process(&mut self, line: &[i32], mic: &[i32], dac: &[i32], adc: &mut [i32]) -> usize {
+        let mut frames = 0;
+        for (index, out) in adc.chunks_exact_mut(2).enumerate() {
+            let stereo = |samples: &[i32]| {
+                [
+                    samples.get(2 * index).copied().unwrap_or(0),
+                    samples.get(2 * index + 1).copied().unwrap_or(0),
+                ]
+            };
+            let output = self.step(InputFrame {
+                line: stereo(line),
+                mic: mic.get(index).copied().unwrap_or(0),
+                dac: stereo(dac),
+            });
+            out.copy_from_slice(&output.adc);
+            frames += 1;
+        }
+        frames
+    }
+
+    /// Peak levels since creation or the last [`Simulator::reset_levels`].
+    pub fn levels(&self) -> SimLevels {
+        let adc_full_scale = self.registers.digital_audio_interface.iwl().full_scale();
+        let db = |peaks: [u32; 2], full_scale: u32| {
+            [
+                Centibels::from_ratio(peaks[0], full_scale),
+                Centibels::from_ratio(peaks[1], full_scale),
+            ]
+        };
+        SimLevels {
+            headphone: db(self.peaks[0], FULL_SCALE),
+            line_out: db(self.peaks[1], FULL_SCALE),
+            adc: db(self.peaks[2], adc_full_scale),
+        }
+    }
+
+    pub fn reset_levels(&mut self) {
+        self.peaks = [[0; 2]; 3];
+    }
+}
+
+fn apply_gain(signal: i64, gain: Option<Centibels>) -> i64 {
+    match gain {
+        Some(gain) => (signal * math::centibels_to_ratio_q16(gain.0 as i32) as i64) >> 16,
+        None => 0,
+    }
+}
+
+fn saturate(signal: i64) -> i32 {
+    signal.clamp(i32::MIN as i64, i32::MAX as i64) as i32
+}
+
+/// De-emphasis filter coefficients `(b0, b1, a1)` in Q30: a 50/15us shelf by bilinear transform
+/// at the selected frequency. At another sampling frequency, the response is only shifted.
+fn deemphasis(deemp: DeempV) -> Option<(i64, i64, i64)> {
+    let fs = match deemp {
+        DeempV::Disable => return None,
+        DeempV::F32k => 32_000,
+        DeempV::F44k1 => 44_100,
+        DeempV::F48k => 48_000,
+    };
+    let k = 2 * fs as i64;
+    let den = 1_000_000 + k * 50;
+    let q30 = |num: i64| (num << 30) / den;
+    Some((
+        q30(1_000_000 + k * 15),
+        q30(1_000_000 - k * 15),
+        q30(1_000_000 - k * 50),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::{Generator, Waveform};
+    use crate::interface::mock::{FrameLog, NoDelay};
+    use crate::power::UseCase;
+    use crate::{HpVoldB, InVoldB, IwlV, SideAttdB};
+
+    fn codec(use_case: UseCase) -> Wm8731<FrameLog> {
+        let mut codec = Wm8731::new(FrameLog::new());
+        codec.set_iwl(IwlV::Iwl16Bits);
+        codec.power_up_use_case(use_case, &mut NoDelay::default());
+        codec
+    }
+
+    /// Peak levels of 100ms of a 1kHz tone at -20dBFS on every input.
+    fn run(simulator: &mut Simulator) -> SimLevels {
+        let mut generator = Generator::new(
+            Waveform::Sine { hz: 1000 },
+            Centibels(-200),
+            48_000,
+            IwlV::Iwl32Bits,
+        );
+        let mut dac_generator = generator.clone();
+        dac_generator.set_level(Centibels::ZERO);
+        simulator.reset_levels();
+        for _ in 0..4800 {
+            let analogue = generator.next_sample();
+            let dac = dac_generator.next_sample() >> 16;
+            simulator.step(InputFrame {
+                line: [analogue; 2],
+                mic: analogue,
+                dac: [dac; 2],
+            });
+        }
+        simulator.levels()
+    }
+
+    fn near(level: Centibels, expected: i16) -> bool {
+        (level.0 - expected).abs() <= 2
+    }
+
+    #[test]
+    fn playback_test() {
+        let mut codec = codec(UseCase::Playback);
+        codec.set_dacmu(false);
+        codec.set_both_hpvol(HpVoldB::N6DB, false);
+        let mut simulator = Simulator::new(codec.snapshot(), 48_000);
+        let levels = run(&mut simulator);
+        assert!(near(levels.line_out[0], 0));
+        assert!(near(levels.headphone[1], -60));
+        // nothing routed to the ADC since it is powered down
+        assert_eq!(levels.adc[0], Centibels(i16::MIN));
+        codec.set_dacmu(true);
+        simulator.sync_codec(&codec);
+        run(&mut simulator);
+        let levels = run(&mut simulator);
+        assert_eq!(levels.line_out[0], Centibels(i16::MIN));
+        codec.set_deemp(DeempV::F48k);
+        codec.set_dacmu(false);
+        simulator.sync_codec(&codec);
+        run(&mut simulator);
+        let levels = run(&mut simulator);
+        // the 50/15us shelf is about -0.4dB at 1kHz
+        assert!(levels.line_out[0].0 < -2 && levels.line_out[0].0 > -10);
+    }
+
+    #[test]
+    fn record_test() {
+        let mut codec = codec(UseCase::RecordLine);
+        codec.set_both_inmute(false);
+        codec.set_both_invol(InVoldB::P6DB);
+        let mut simulator = Simulator::new(codec.snapshot(), 48_000);
+        let levels = run(&mut simulator);
+        assert!(near(levels.adc[0], -140));
+        assert_eq!(levels.line_out[0], Centibels(i16::MIN));
+        codec.set_left_inmute(true);
+        simulator.sync_codec(&codec);
+        let levels = run(&mut simulator);
+        // only the high pass filter tail is left
+        assert!(levels.adc[0].0 < -600);
+        assert!(near(levels.adc[1], -140));
+        codec.set_insel(InselV::Mic);
+        codec.set_mutemic(false);
+        codec.set_micboost(true);
+        codec.set_micpd(false);
+        simulator.sync_codec(&codec);
+        let levels = run(&mut simulator);
+        assert!(near(levels.adc[0], 0));
+    }
+
+    #[test]
+    fn monitoring_test() {
+        let mut codec = codec(UseCase::Bypass);
+        codec.set_both_inmute(false);
+        codec.set_both_hpvol(HpVoldB::Z0DB, false);
+        let mut simulator = Simulator::new(codec.snapshot(), 48_000);
+        let levels = run(&mut simulator);
+        assert!(near(levels.headphone[0], -200));
+        codec.set_bypass(false);
+        codec.set_micpd(false);
+        codec.set_sidetone(true);
+        codec.set_sideatt(SideAttdB::N12DB);
+        simulator.sync_codec(&codec);
+        let levels = run(&mut simulator);
+        assert!(near(levels.headphone[0], -320));
+    }
+
+    #[test]
+    fn high_pass_test() {
+        let mut codec = codec(UseCase::RecordLine);
+        codec.set_both_inmute(false);
+        codec.set_both_invol(InVoldB::Z0DB);
+        let mut simulator = Simulator::new(codec.snapshot(), 48_000);
+        let dc = InputFrame {
+            line: [1 << 28; 2],
+            ..InputFrame::default()
+        };
+        for _ in 0..48_000 {
+            simulator.step(dc);
+        }
+        // offset removed by the filter
+        assert!(simulator.step(dc).adc[0].abs() < 10);
+        codec.set_adc_high_pass(AdcHighPass::FrozenOffset);
+        simulator.sync_codec(&codec);
+        assert!(simulator.step(dc).adc[0].abs() < 10);
+        codec.set_adc_high_pass(AdcHighPass::Disabled);
+        simulator.sync_codec(&codec);
+        assert_eq!(simulator.step(dc).adc[0], 1 << 12);
+    }
+}
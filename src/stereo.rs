@@ -0,0 +1,288 @@
+//! Stereo volume with master, balance and link.
+//!
+//! [`StereoVolume`] compute left and right volumes from a master volume and a balance, and write
+//! them with as few frames as possible. It works for headphone out ([`HpVoldB`]) and line in
+//! ([`InVoldB`]).
+use crate::db::{Centibels, Rounding};
+use crate::gain::Gain;
+use crate::interface::WriteFrame;
+use crate::math;
+use crate::{HpVoldB, InVoldB, Wm8731};
+
+/// Balance of the left side only.
+pub const BALANCE_LEFT: i8 = -100;
+/// Balance of the right side only.
+pub const BALANCE_RIGHT: i8 = 100;
+
+/// How balance attenuate each side.
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Default)]
+pub enum PanLaw {
+    /// Both sides at master volume when centred, the side opposite to the balance is attenuated.
+    #[default]
+    Balance,
+    /// Constant power, both sides at -3dB when centred.
+    ConstantPower,
+    /// Constant amplitude, both sides at -6dB when centred.
+    Linear,
+}
+
+impl PanLaw {
+    /// Attenuation of a side getting `share` of the signal, out of 200. `None` is silence.
+    fn attenuation(self, share: u32) -> Option<Centibels> {
+        if share == 0 {
+            return None;
+        }
+        let cb = match self {
+            PanLaw::Balance => math::ratio_to_centibels(share.min(100), 100),
+            PanLaw::ConstantPower => math::ratio_to_centibels(share, 200) / 2,
+            PanLaw::Linear => math::ratio_to_centibels(share, 200),
+        };
+        Some(Centibels(cb as i16))
+    }
+}
+
+/// A gain stage with left and right channels.
+pub trait StereoGain: Gain {
+    fn left<I: WriteFrame>(codec: &Wm8731<I>) -> Self;
+    fn right<I: WriteFrame>(codec: &Wm8731<I>) -> Self;
+    fn set_left<I: WriteFrame>(self, codec: &mut Wm8731<I>, zcen: bool);
+    fn set_right<I: WriteFrame>(self, codec: &mut Wm8731<I>, zcen: bool);
+    /// Set both channels with a single frame.
+    fn set_both<I: WriteFrame>(self, codec: &mut Wm8731<I>, zcen: bool);
+    /// Value the codec stores when set to `self`, after its volume limit.
+    fn limited<I: WriteFrame>(self, _codec: &Wm8731<I>) -> Self {
+        self
+    }
+}
+
+impl StereoGain for HpVoldB {
+    fn left<I: WriteFrame>(codec: &Wm8731<I>) -> Self {
+        codec.left_hpvol()
+    }
+    fn right<I: WriteFrame>(codec: &Wm8731<I>) -> Self {
+        codec.right_hpvol()
+    }
+    fn set_left<I: WriteFrame>(self, codec: &mut Wm8731<I>, zcen: bool) {
+        codec.set_left_hpvol(self, zcen)
+    }
+    fn set_right<I: WriteFrame>(self, codec: &mut Wm8731<I>, zcen: bool) {
+        codec.set_right_hpvol(self, zcen)
+    }
+    fn set_both<I: WriteFrame>(self, codec: &mut Wm8731<I>, zcen: bool) {
+        codec.set_both_hpvol(self, zcen)
+    }
+    fn limited<I: WriteFrame>(self, codec: &Wm8731<I>) -> Self {
+        match codec.hpvol_cap() {
+            Some(cap) if self.into_raw() > cap.into_raw() => cap,
+            _ => self,
+        }
+    }
+}
+
+/// Line in has no zero cross detection, `zcen` is ignored.
+impl StereoGain for InVoldB {
+    fn left<I: WriteFrame>(codec: &Wm8731<I>) -> Self {
+        codec.left_invol()
+    }
+    fn right<I: WriteFrame>(codec: &Wm8731<I>) -> Self {
+        codec.right_invol()
+    }
+    fn set_left<I: WriteFrame>(self, codec: &mut Wm8731<I>, _zcen: bool) {
+        codec.set_left_invol(self)
+    }
+    fn set_right<I: WriteFrame>(self, codec: &mut Wm8731<I>, _zcen: bool) {
+        codec.set_right_invol(self)
+    }
+    fn set_both<I: WriteFrame>(self, codec: &mut Wm8731<I>, _zcen: bool) {
+        codec.set_both_invol(self)
+    }
+}
+
+/// Stereo volume controller.
+///
+/// Setters write the codec immediately. In link mode, balance is ignored and both sides always
+/// follow master.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct StereoVolume<G> {
+    master: G,
+    balance: i8,
+    pan_law: PanLaw,
+    link: bool,
+    zcen: bool,
+}
+
+impl<G: StereoGain> StereoVolume<G> {
+    /// Centred, unlinked controller using [`PanLaw::Balance`] and no zero cross detection.
+    pub const fn new(master: G) -> Self {
+        Self {
+            master,
+            balance: 0,
+            pan_law: PanLaw::Balance,
+            link: false,
+            zcen: false,
+        }
+    }
+
+    pub const fn with_pan_law(self, pan_law: PanLaw) -> Self {
+        Self { pan_law, ..self }
+    }
+
+    /// Use zero cross detection for headphone volume changes.
+    pub const fn with_zcen(self, zcen: bool) -> Self {
+        Self { zcen, ..self }
+    }
+
+    pub fn master(&self) -> G {
+        self.master
+    }
+
+    /// Balance from [`BALANCE_LEFT`] to [`BALANCE_RIGHT`], 0 is centred.
+    pub fn balance(&self) -> i8 {
+        self.balance
+    }
+
+    pub fn pan_law(&self) -> PanLaw {
+        self.pan_law
+    }
+
+    pub fn is_linked(&self) -> bool {
+        self.link
+    }
+
+    /// Left and right volumes resulting from master, balance and pan law.
+    pub fn channels(&self) -> (G, G) {
+        if self.link {
+            return (self.master, self.master);
+        }
+        let left = 100 - self.balance.clamp(BALANCE_LEFT, BALANCE_RIGHT) as i32;
+        let side = |share: i32| match (
+            self.master.to_centibels(),
+            self.pan_law.attenuation(share as u32),
+        ) {
+            (Some(master), Some(att)) => G::from_centibels(master + att, Rounding::Nearest),
+            // mute for headphone, lowest gain for line in
+            _ => G::MIN,
+        };
+        (side(left), side(200 - left))
+    }
+
+    pub fn set_master<I: WriteFrame>(&mut self, codec: &mut Wm8731<I>, master: G) {
+        self.master = master;
+        self.apply(codec);
+    }
+
+    /// Set balance, saturated to [`BALANCE_LEFT`]..=[`BALANCE_RIGHT`].
+    pub fn set_balance<I: WriteFrame>(&mut self, codec: &mut Wm8731<I>, balance: i8) {
+        self.balance = balance.clamp(BALANCE_LEFT, BALANCE_RIGHT);
+        self.apply(codec);
+    }
+
+    pub fn set_pan_law<I: WriteFrame>(&mut self, codec: &mut Wm8731<I>, pan_law: PanLaw) {
+        self.pan_law = pan_law;
+        self.apply(codec);
+    }
+
+    pub fn set_link<I: WriteFrame>(&mut self, codec: &mut Wm8731<I>, link: bool) {
+        self.link = link;
+        self.apply(codec);
+    }
+
+    /// Set left volume. In link mode this set master, and both sides with a single frame.
+    ///
+    /// Otherwise only left side is written, master and balance will apply again on their next
+    /// change.
+    pub fn set_left<I: WriteFrame>(&mut self, codec: &mut Wm8731<I>, volume: G) {
+        if self.link {
+            self.set_master(codec, volume);
+        } else {
+            volume.set_left(codec, self.zcen);
+        }
+    }
+
+    /// Set right volume. In link mode this set master, and both sides with a single frame.
+    ///
+    /// Otherwise only right side is written, master and balance will apply again on their next
+    /// change.
+    pub fn set_right<I: WriteFrame>(&mut self, codec: &mut Wm8731<I>, volume: G) {
+        if self.link {
+            self.set_master(codec, volume);
+        } else {
+            volume.set_right(codec, self.zcen);
+        }
+    }
+
+    /// Write the volumes to the codec, only where they differ from the current ones once the
+    /// codec volume limit is applied.
+    pub fn apply<I: WriteFrame>(&self, codec: &mut Wm8731<I>) {
+        let (left, right) = self.channels();
+        let left_changed = G::left(codec) != left.limited(codec);
+        let right_changed = G::right(codec) != right.limited(codec);
+        if left == right && (left_changed || right_changed) {
+            left.set_both(codec, self.zcen);
+        } else {
+            if left_changed {
+                left.set_left(codec, self.zcen);
+            }
+            if right_changed {
+                right.set_right(codec, self.zcen);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interface::mock::FrameLog;
+    use crate::VolumeLimit;
+
+    #[test]
+    fn balance_test() {
+        let mut codec = Wm8731::new(FrameLog::new());
+        let mut volume = StereoVolume::new(HpVoldB::N10DB);
+        volume.apply(&mut codec);
+        assert_eq!(codec.both_hpvol(), (HpVoldB::N10DB, HpVoldB::N10DB));
+        volume.set_balance(&mut codec, 50);
+        assert_eq!(codec.both_hpvol(), (HpVoldB::N16DB, HpVoldB::N10DB));
+        volume.set_balance(&mut codec, BALANCE_LEFT);
+        assert_eq!(codec.both_hpvol(), (HpVoldB::N10DB, HpVoldB::MUTE));
+        volume.set_balance(&mut codec, 0);
+        volume.set_pan_law(&mut codec, PanLaw::ConstantPower);
+        assert_eq!(codec.both_hpvol(), (HpVoldB::N13DB, HpVoldB::N13DB));
+        volume.set_pan_law(&mut codec, PanLaw::Linear);
+        assert_eq!(codec.both_hpvol(), (HpVoldB::N16DB, HpVoldB::N16DB));
+    }
+
+    #[test]
+    fn link_test() {
+        let mut codec = Wm8731::new(FrameLog::new());
+        let mut volume = StereoVolume::new(InVoldB::Z0DB);
+        volume.set_balance(&mut codec, -50);
+        volume.set_link(&mut codec, true);
+        codec.interface.clear();
+        volume.set_right(&mut codec, InVoldB::N3DB);
+        assert_eq!(codec.interface.frames().len(), 1);
+        assert_eq!(codec.both_invol(), (InVoldB::N3DB, InVoldB::N3DB));
+        assert_eq!(volume.master(), InVoldB::N3DB);
+        volume.set_link(&mut codec, false);
+        assert_eq!(codec.both_invol(), (InVoldB::N3DB, InVoldB::N9DB));
+    }
+
+    #[test]
+    fn limit_test() {
+        let mut codec = Wm8731::new(FrameLog::new());
+        codec.set_bypass(false);
+        codec.set_volume_limit(Some(VolumeLimit::new(HpVoldB::N20DB)));
+        let mut volume = StereoVolume::new(HpVoldB::Z0DB);
+        volume.set_balance(&mut codec, 50);
+        assert_eq!(codec.both_hpvol(), (HpVoldB::N20DB, HpVoldB::N20DB));
+        // codec already holds the capped volumes
+        codec.interface.clear();
+        volume.apply(&mut codec);
+        volume.set_balance(&mut codec, 20);
+        assert!(codec.interface.frames().is_empty());
+        volume.set_balance(&mut codec, BALANCE_RIGHT);
+        assert_eq!(codec.both_hpvol(), (HpVoldB::MUTE, HpVoldB::N20DB));
+        assert_eq!(codec.interface.frames().len(), 1);
+    }
+}
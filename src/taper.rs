@@ -0,0 +1,140 @@
+//! Volume tapers for user facing controls.
+//!
+//! [`Gain::from_scaled`] maps a user range linearly onto register codes, which is linear in dB.
+//! A [`Taper`] maps a user value in `0..=max`, like a knob position or a slider, to a gain with a
+//! chosen curve. Value 0 is always mute.
+//!
+//! `InVoldB` has no mute code, so tapers return `None` for mute and the caller mute the input
+//! with [`Wm8731::set_left_inmute`](crate::Wm8731::set_left_inmute) or similar. For `HpVoldB`,
+//! [`Taper::hp_vol`] return [`HpVoldB::MUTE`] directly.
+use crate::db::{Centibels, Rounding};
+use crate::gain::Gain;
+use crate::math;
+use crate::HpVoldB;
+
+/// Curve from a user value to a gain.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Taper<'a> {
+    /// Same dB change for each user step, from the lowest to the highest gain.
+    LinearDb,
+    /// Amplitude proportional to the square of the user value, like an audio log potentiometer.
+    /// This gives fine control at the top of the range and coarse control at the bottom.
+    AudioLog,
+    /// Custom curve. The table gives gains for user values spread evenly from 1 to `max`, values
+    /// in between are interpolated. An empty table always mute.
+    Table(&'a [Centibels]),
+}
+
+impl Taper<'_> {
+    /// Map `value` in `0..=max` to a gain in `floor..=ceiling`. Return `None` for mute.
+    ///
+    /// Value 1 gives `floor` and `max` gives `ceiling`. `value` is saturated to `max`.
+    /// `floor` and `ceiling` are unused by [`Taper::Table`].
+    pub fn centibels(
+        &self,
+        value: u16,
+        max: u16,
+        floor: Centibels,
+        ceiling: Centibels,
+    ) -> Option<Centibels> {
+        let value = value.min(max);
+        if value == 0 {
+            return None;
+        }
+        let cb = match self {
+            Taper::LinearDb => interpolate(floor.0, ceiling.0, value - 1, max - 1),
+            Taper::AudioLog => {
+                let cb = ceiling.0 as i32 + 2 * math::ratio_to_centibels(value as u32, max as u32);
+                cb.max(floor.0 as i32) as i16
+            }
+            Taper::Table(table) => {
+                let last = table.len().checked_sub(1)?;
+                if max == 1 {
+                    table[last].0
+                } else {
+                    // position in the table in 1/256 of entry
+                    let pos = (value as u64 - 1) * 256 * last as u64 / (max as u64 - 1);
+                    let index = (pos / 256) as usize;
+                    let next = (index + 1).min(last);
+                    interpolate(table[index].0, table[next].0, (pos % 256) as u16, 256)
+                }
+            }
+        };
+        Some(Centibels(cb))
+    }
+
+    /// Map `value` in `0..=max` to any gain type, over its full range. Return `None` for mute.
+    pub fn gain<G: Gain>(&self, value: u16, max: u16) -> Option<G> {
+        let (floor, ceiling) = range::<G>();
+        self.centibels(value, max, floor, ceiling)
+            .map(|cb| G::from_centibels(cb, Rounding::Nearest))
+    }
+
+    /// Map `value` in `0..=max` to a headphone volume, using the mute code for 0.
+    pub fn hp_vol(&self, value: u16, max: u16) -> HpVoldB {
+        self.gain(value, max).unwrap_or(HpVoldB::MUTE)
+    }
+}
+
+/// Linear interpolation from `a` to `b`, at `num / den`.
+fn interpolate(a: i16, b: i16, num: u16, den: u16) -> i16 {
+    if den == 0 {
+        return b;
+    }
+    let span = b as i32 - a as i32;
+    let offset = (2 * span * num as i32 + den as i32) / (2 * den as i32);
+    (a as i32 + offset) as i16
+}
+
+/// Lowest and highest gain of a gain type, ignoring mute.
+fn range<G: Gain>() -> (Centibels, Centibels) {
+    let gains = G::steps().filter_map(G::to_centibels);
+    let floor = gains.clone().min().unwrap_or_default();
+    let ceiling = gains.max().unwrap_or_default();
+    (floor, ceiling)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::InVoldB;
+
+    #[test]
+    fn taper_test() {
+        let taper = Taper::LinearDb;
+        assert_eq!(taper.hp_vol(0, 100), HpVoldB::MUTE);
+        assert_eq!(taper.hp_vol(1, 100), HpVoldB::N73DB);
+        assert_eq!(taper.hp_vol(100, 100), HpVoldB::P6DB);
+        assert_eq!(taper.hp_vol(500, 100), HpVoldB::P6DB);
+        assert_eq!(taper.gain::<InVoldB>(0, 10), None);
+        assert_eq!(taper.gain::<InVoldB>(10, 10), Some(InVoldB::P12DB));
+
+        // log taper has finer steps at the top
+        let taper = Taper::AudioLog;
+        assert_eq!(taper.hp_vol(100, 100), HpVoldB::P6DB);
+        assert_eq!(taper.hp_vol(50, 100), HpVoldB::N6DB);
+        assert_eq!(taper.hp_vol(90, 100), HpVoldB::P4DB);
+        assert_eq!(taper.hp_vol(1, 100), HpVoldB::N73DB);
+
+        let table = [Centibels(-400), Centibels(-200), Centibels(0)];
+        let taper = Taper::Table(&table);
+        assert_eq!(taper.hp_vol(1, 5), HpVoldB::N40DB);
+        assert_eq!(taper.hp_vol(2, 5), HpVoldB::N30DB);
+        assert_eq!(taper.hp_vol(3, 5), HpVoldB::N20DB);
+        assert_eq!(taper.hp_vol(5, 5), HpVoldB::Z0DB);
+        assert_eq!(Taper::Table(&[]).hp_vol(5, 5), HpVoldB::MUTE);
+
+        // long table and full u16 range
+        let mut table = [Centibels(0); 301];
+        for (i, cb) in table.iter_mut().enumerate() {
+            *cb = Centibels(i as i16 - 300);
+        }
+        let taper = Taper::Table(&table);
+        let max = u16::MAX;
+        assert_eq!(taper.gain::<InVoldB>(max, max), Some(InVoldB::Z0DB));
+        assert_eq!(
+            taper.gain::<InVoldB>(max / 2 + 1, max),
+            Some(InVoldB::N15DB)
+        );
+    }
+}
@@ -73,6 +73,7 @@ mod app {
     use hal::spi::Spi;
     use rtt_target::{rprintln, rtt_init, set_print_channel};
     use setup::*;
+    use wm8731_another_hal::clock::{ClkoutDiv, ClockConfig, ClockSource};
     use wm8731_another_hal::prelude::*;
     use wm8731_another_hal_test::*;
 
@@ -83,6 +84,9 @@ mod app {
     //generate Master Clock ? Modifying this require to adapt the i2s clock
     const MCK: bool = true;
 
+    //Frequency of the generated Master Clock, i2s_clk / (2 * I2SDIV + ODD)
+    const MCLK_HZ: u32 = 12_288_000;
+
     #[shared]
     struct Shared {
         i2s2: SPI2,
@@ -168,10 +172,7 @@ mod app {
         exti.rtsr.modify(|_, w| w.tr12().set_bit());
 
         let mut i2s2 = device.SPI2;
-        setup_i2s2(&mut i2s2, I2SDIV, ODD, MCK);
-
         let mut i2s2ext = device.I2S2EXT;
-        setup_i2s2ext(&mut i2s2ext, I2SDIV, ODD, MCK);
 
         //Spi com
         let pa5 = gpioa.pa5.into_alternate(); //_af5(); //CK
@@ -204,8 +205,6 @@ mod app {
             wm8731.set_micpd(false);
             wm8731.set_adcpd(false);
             wm8731.set_dacpd(false);
-            wm8731.set_oscpd(false);
-            wm8731.set_clkoutpd(false);
             wm8731.set_poweroff(false);
             rprintln!("Mute headphone");
             wm8731.set_both_hpvol(HpVoldB::MUTE, false);
@@ -232,8 +231,11 @@ mod app {
             wm8731.set_bclkinv(false);
             //sampling
             wm8731.set_sampling_rates(SamplingRates::ADC256_DAC256_A);
-            wm8731.set_clkidiv2(false);
-            wm8731.set_clkodiv2(false);
+            //MCLK is generated by the MCU
+            wm8731.set_clock_config(
+                ClockConfig::new(ClockSource::ExternalMclk { hz: MCLK_HZ })
+                    .with_clkout(Some(ClkoutDiv::Div1)),
+            );
             rprintln!("Out power up");
             wm8731.set_outpd(false);
             delay.delay_ms(100_u32);
@@ -245,6 +247,11 @@ mod app {
                 delay.delay_ms(10_u32);
             }
         }
+        //Derive i2s2 and i2s2_ext configuration from the codec one
+        let link = wm8731.link_descriptor(MCLK_HZ).unwrap();
+        setup_i2s2(&mut i2s2, &link, I2SDIV, ODD, MCK);
+        setup_i2s2ext(&mut i2s2ext, &link, I2SDIV, ODD, MCK);
+
         wm8731.activate();
         i2s2ext.i2scfgr.modify(|_, w| w.i2se().enabled());
         i2s2.i2scfgr.modify(|_, w| w.i2se().enabled());
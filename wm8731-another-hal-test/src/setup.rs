@@ -1,8 +1,38 @@
 use stm32f4xx_hal as hal;
 
+use hal::pac::spi1::i2scfgr;
 use hal::pac::{I2S2EXT, SPI2};
+use wm8731_another_hal::link::{ClockPolarity, LinkDescriptor, LinkStandard};
 
-pub fn setup_i2s2(spi2: &mut SPI2, i2sdiv: u8, odd: bool, mck: bool) {
+/// Write I2S frame format fields derived from the codec link description. Panic if the link
+/// can't be handled by the I2S peripheral.
+fn write_format<'w>(w: &'w mut i2scfgr::W, link: &LinkDescriptor) -> &'w mut i2scfgr::W {
+    match link.standard {
+        LinkStandard::I2s => w.i2sstd().philips().pcmsync().long(),
+        LinkStandard::LeftJustified => w.i2sstd().msb().pcmsync().long(),
+        LinkStandard::RightJustified => w.i2sstd().lsb().pcmsync().long(),
+        LinkStandard::Pcm { data_delay: 1 } => w.i2sstd().pcm().pcmsync().short(),
+        LinkStandard::Pcm { .. } => panic!("unsupported PCM data delay"),
+    };
+    // idle_high is what works with a not inverted codec bit clock
+    match link.clock_polarity {
+        ClockPolarity::SampleOnRising => w.ckpol().idle_high(),
+        ClockPolarity::SampleOnFalling => w.ckpol().idle_low(),
+    };
+    match link.data_bits {
+        16 => w.datlen().sixteen_bit(),
+        24 => w.datlen().twenty_four_bit(),
+        32 => w.datlen().thirty_two_bit(),
+        _ => panic!("unsupported data length"),
+    };
+    match link.channel_bits {
+        16 => w.chlen().sixteen_bit(),
+        32 => w.chlen().thirty_two_bit(),
+        _ => panic!("unsupported channel length"),
+    }
+}
+
+pub fn setup_i2s2(spi2: &mut SPI2, link: &LinkDescriptor, i2sdiv: u8, odd: bool, mck: bool) {
     //i2s2 interrupt and dma
     spi2.cr2.modify(|_, w| {
         w.txeie().clear_bit();
@@ -18,17 +48,23 @@ pub fn setup_i2s2(spi2: &mut SPI2, i2sdiv: u8, odd: bool, mck: bool) {
     });
     spi2.i2scfgr.modify(|_, w| {
         w.i2smod().i2smode(); //
-        w.i2scfg().master_rx(); //
-        w.pcmsync().long(); //
-        w.i2sstd().philips(); //
-        w.ckpol().idle_high(); //
-        w.datlen().sixteen_bit(); //
-        w.chlen().sixteen_bit(); //
+        if link.host_is_master() {
+            w.i2scfg().master_rx(); //
+        } else {
+            w.i2scfg().slave_rx(); //
+        }
+        write_format(w, link);
         w.i2se().disabled()
     });
 }
 
-pub fn setup_i2s2ext(i2s2ext: &mut I2S2EXT, i2sdiv: u8, odd: bool, mck: bool) {
+pub fn setup_i2s2ext(
+    i2s2ext: &mut I2S2EXT,
+    link: &LinkDescriptor,
+    i2sdiv: u8,
+    odd: bool,
+    mck: bool,
+) {
     //i2s2_ext interrupt and dma
     i2s2ext.cr2.modify(|_, w| {
         w.txeie().set_bit();
@@ -45,11 +81,7 @@ pub fn setup_i2s2ext(i2s2ext: &mut I2S2EXT, i2sdiv: u8, odd: bool, mck: bool) {
     i2s2ext.i2scfgr.modify(|_, w| {
         w.i2smod().i2smode(); //
         w.i2scfg().slave_tx(); //
-        w.pcmsync().long(); //
-        w.i2sstd().philips(); //
-        w.ckpol().idle_high(); //
-        w.datlen().sixteen_bit(); //
-        w.chlen().sixteen_bit(); //
+        write_format(w, link);
         w.i2se().disabled()
     });
 }